@@ -0,0 +1 @@
+CREATE UNIQUE INDEX follows_public_key_target ON follows(public_key, target);
@@ -0,0 +1,9 @@
+CREATE TABLE profiles(
+    public_key TEXT PRIMARY KEY,
+    name TEXT,
+    name_sequence INTEGER,
+    description TEXT,
+    description_sequence INTEGER,
+    avatar TEXT,
+    avatar_sequence INTEGER
+);
@@ -0,0 +1,2 @@
+CREATE TABLE blocks(public_key TEXT, target TEXT, state BOOLEAN, sequence INTEGER);
+CREATE UNIQUE INDEX blocks_public_key_target ON blocks(public_key, target);
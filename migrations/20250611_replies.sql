@@ -0,0 +1,2 @@
+CREATE TABLE replies(id TEXT PRIMARY KEY, root TEXT, parent TEXT);
+CREATE INDEX replies_root ON replies(root);
@@ -0,0 +1,3 @@
+CREATE TABLE reactions(public_key TEXT, target TEXT, reaction TEXT, state BOOLEAN, sequence INTEGER);
+CREATE UNIQUE INDEX reactions_public_key_target_reaction ON reactions(public_key, target, reaction);
+CREATE INDEX reactions_target ON reactions(target);
@@ -0,0 +1,3 @@
+CREATE TABLE post_revisions(id TEXT PRIMARY KEY, target TEXT, public_key TEXT, timestamp INTEGER, sequence INTEGER, body TEXT);
+CREATE INDEX post_revisions_target ON post_revisions(target);
+ALTER TABLE posts ADD COLUMN deleted BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,2 @@
+CREATE TABLE unknown_events(id TEXT PRIMARY KEY, public_key TEXT, event_type TEXT, raw BLOB);
+CREATE TABLE quarantine(id TEXT PRIMARY KEY, public_key TEXT, sequence INTEGER, reason TEXT, raw BLOB);
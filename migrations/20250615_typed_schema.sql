@@ -0,0 +1,111 @@
+-- Rebuild the app tables with primary keys, consistent column types and indexes. SQLite can't
+-- alter constraints in place, so every table is renamed, recreated and copied over.
+DROP INDEX follows_public_key_target;
+DROP INDEX blocks_public_key_target;
+DROP INDEX replies_root;
+DROP INDEX reactions_public_key_target_reaction;
+DROP INDEX reactions_target;
+DROP INDEX post_revisions_target;
+
+ALTER TABLE posts RENAME TO posts_old;
+ALTER TABLE replies RENAME TO replies_old;
+ALTER TABLE follows RENAME TO follows_old;
+ALTER TABLE blocks RENAME TO blocks_old;
+ALTER TABLE reactions RENAME TO reactions_old;
+ALTER TABLE post_revisions RENAME TO post_revisions_old;
+ALTER TABLE profiles RENAME TO profiles_old;
+
+CREATE TABLE posts(
+    id TEXT PRIMARY KEY NOT NULL,
+    public_key TEXT NOT NULL,
+    timestamp INTEGER NOT NULL,
+    body TEXT NOT NULL,
+    deleted BOOLEAN NOT NULL DEFAULT FALSE
+);
+CREATE INDEX posts_public_key ON posts(public_key);
+CREATE INDEX posts_timestamp ON posts(timestamp, id);
+
+-- Replies may point at a root we have not replicated yet, but always belong to a post we have
+CREATE TABLE replies(
+    id TEXT PRIMARY KEY NOT NULL REFERENCES posts(id) ON DELETE CASCADE,
+    root TEXT NOT NULL,
+    parent TEXT NOT NULL
+);
+CREATE INDEX replies_root ON replies(root);
+
+CREATE TABLE post_revisions(
+    id TEXT PRIMARY KEY NOT NULL,
+    target TEXT NOT NULL,
+    public_key TEXT NOT NULL,
+    timestamp INTEGER NOT NULL,
+    sequence INTEGER NOT NULL,
+    body TEXT
+);
+CREATE INDEX post_revisions_target ON post_revisions(target);
+
+CREATE TABLE follows(
+    public_key TEXT NOT NULL,
+    target TEXT NOT NULL,
+    state BOOLEAN NOT NULL,
+    sequence INTEGER NOT NULL,
+    PRIMARY KEY (public_key, target)
+);
+CREATE INDEX follows_target ON follows(target);
+
+CREATE TABLE blocks(
+    public_key TEXT NOT NULL,
+    target TEXT NOT NULL,
+    state BOOLEAN NOT NULL,
+    sequence INTEGER NOT NULL,
+    PRIMARY KEY (public_key, target)
+);
+
+CREATE TABLE reactions(
+    public_key TEXT NOT NULL,
+    target TEXT NOT NULL,
+    reaction TEXT NOT NULL,
+    state BOOLEAN NOT NULL,
+    sequence INTEGER NOT NULL,
+    PRIMARY KEY (public_key, target, reaction)
+);
+CREATE INDEX reactions_target ON reactions(target);
+
+CREATE TABLE profiles(
+    public_key TEXT PRIMARY KEY NOT NULL,
+    name TEXT,
+    name_sequence INTEGER,
+    description TEXT,
+    description_sequence INTEGER,
+    avatar TEXT,
+    avatar_sequence INTEGER
+);
+
+INSERT OR IGNORE INTO posts ( id, public_key, timestamp, body, deleted )
+SELECT id, public_key, CAST(timestamp AS INTEGER), body, deleted FROM posts_old
+WHERE id IS NOT NULL AND public_key IS NOT NULL;
+
+INSERT OR IGNORE INTO replies ( id, root, parent )
+SELECT id, root, parent FROM replies_old WHERE id IN (SELECT id FROM posts);
+
+INSERT OR IGNORE INTO post_revisions ( id, target, public_key, timestamp, sequence, body )
+SELECT id, target, public_key, timestamp, sequence, body FROM post_revisions_old;
+
+INSERT OR IGNORE INTO follows ( public_key, target, state, sequence )
+SELECT public_key, target, state, sequence FROM follows_old;
+
+INSERT OR IGNORE INTO blocks ( public_key, target, state, sequence )
+SELECT public_key, target, state, sequence FROM blocks_old;
+
+INSERT OR IGNORE INTO reactions ( public_key, target, reaction, state, sequence )
+SELECT public_key, target, reaction, state, sequence FROM reactions_old;
+
+INSERT OR IGNORE INTO profiles
+SELECT * FROM profiles_old;
+
+DROP TABLE posts_old;
+DROP TABLE replies_old;
+DROP TABLE post_revisions_old;
+DROP TABLE follows_old;
+DROP TABLE blocks_old;
+DROP TABLE reactions_old;
+DROP TABLE profiles_old;
@@ -0,0 +1,3 @@
+-- Serve author filtered feeds straight from the index in timestamp order
+DROP INDEX posts_public_key;
+CREATE INDEX posts_public_key_timestamp ON posts(public_key, timestamp, id);
@@ -0,0 +1,8 @@
+-- Invites we handed out, local to this node and not rebuilt from the operation store
+CREATE TABLE invites(
+    id TEXT PRIMARY KEY NOT NULL,
+    created INTEGER NOT NULL,
+    follow_back BOOLEAN NOT NULL,
+    redeemed_by TEXT,
+    followed_back BOOLEAN NOT NULL DEFAULT FALSE
+);
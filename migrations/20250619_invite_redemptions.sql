@@ -0,0 +1,14 @@
+-- Invites of a pub are redeemed by many, so redemptions get a table of their own
+CREATE TABLE invite_redemptions(
+    invite TEXT NOT NULL REFERENCES invites(id),
+    public_key TEXT NOT NULL,
+    followed_back BOOLEAN NOT NULL DEFAULT FALSE,
+    PRIMARY KEY (invite, public_key)
+);
+INSERT INTO invite_redemptions ( invite, public_key, followed_back )
+SELECT id, redeemed_by, followed_back FROM invites WHERE redeemed_by IS NOT NULL;
+
+ALTER TABLE invites DROP COLUMN redeemed_by;
+ALTER TABLE invites DROP COLUMN followed_back;
+-- How often an invite can be redeemed, unlimited when NULL
+ALTER TABLE invites ADD COLUMN uses INTEGER DEFAULT 1;
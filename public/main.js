@@ -1,4 +1,17 @@
-function avatar(key) {
+// everything other peers publish has to be escaped before it goes into innerHTML
+function escapeHtml(text) {
+  return String(text)
+    .replaceAll('&', '&amp;')
+    .replaceAll('<', '&lt;')
+    .replaceAll('>', '&gt;')
+    .replaceAll('"', '&quot;')
+    .replaceAll("'", '&#39;')
+}
+
+function avatar(key, image) {
+  if (image) {
+    return `<img src="data:image;base64,${escapeHtml(image)}" style="width:40px; height: 40px; border-radius: 8px 8px; object-fit: cover;">`
+  }
   return `
   <div style="width:40px; height: 40px; background: #${key.slice(0, 6)}; overflow:hidden; border-radius: 8px 8px;">
     <div style="width:40px; height: 20px; background: #${key.slice(6, 12)}">
@@ -7,70 +20,156 @@ function avatar(key) {
   `
 }
 
-(async () => {
-  console.log('ready')
-
-  let posts = []
+async function postJson(path, data) {
+  const response = await fetch(path, {
+    method: "post",
+    headers: {
+      'Accept': 'application/json',
+      'Content-Type': 'application/json'
+    },
+    //make sure to serialize your JSON body
+    body: JSON.stringify(data)
+  })
+  if (response.status === 401) {
+    alert(`${path} failed: open the login link the node printed on start`)
+  } else if (!response.ok) {
+    const { error } = await response.json()
+    alert(`${path} failed: ${error}`)
+  }
+  return response
+}
 
-  document.getElementById("post-button").onclick = async () => {
-    await fetch("/post", {
-      method: "post",
-      headers: {
-        'Accept': 'application/json',
-        'Content-Type': 'application/json'
-      },
-      //make sure to serialize your JSON body
-      body: JSON.stringify({
-        body: document.getElementById("post-text").value
-      })
-    })
+async function toggleLike(target, liked) {
+  await postJson(liked ? "/unreact" : "/react", { target, reaction: "❤️" })
+}
 
-    window.location = window.location
+async function editPost(target) {
+  const newBody = prompt("Edit post")
+  if (newBody !== null) {
+    await postJson("/edit", { target, new_body: newBody })
   }
+}
 
-  const identity = await (await fetch("/id")).json()
-  console.log(identity)
-
-  const response = await fetch("/posts")
-  const data = await response.json()
+async function deletePost(target) {
+  if (confirm("Delete this post?")) {
+    await postJson("/delete", { target })
+  }
+}
 
-  posts = data
-  // for (logId of Object.keys(data)) {
-  //   for (operation of data[logId]) {
-  //     posts.push(operation)
-  //   }
-  // }
+// posts by id, kept up to date by the events stream
+const posts = new Map()
+let identity = null
 
-  posts = posts.sort((a, b) => {
-    if (a.timestamp < b.timestamp) {
-      return -1
-    }
-    if (b.timestamp < a.timestamp) {
-      return 1
-    }
-    return 0
-  })
+function renderPosts() {
+  const sorted = Array.from(posts.values())
+    .filter(p => !p.deleted)
+    .sort((a, b) => {
+      if (a.timestamp < b.timestamp) {
+        return -1
+      }
+      if (b.timestamp < a.timestamp) {
+        return 1
+      }
+      return 0
+    })
 
   let postsString = ''
 
-  posts.map(p => {
+  sorted.map(p => {
     postsString += `
     <div class="post">
       <div>
         <div class="post-author">
-          ${avatar(p.public_key)}
-          ${p.public_key.slice(0, 9)} ${identity.public_key === p.public_key ? '(Me)' : ''}
+          ${avatar(p.public_key, p.avatar)}
+          ${escapeHtml(p.name || p.public_key.slice(0, 9))} ${identity.public_key === p.public_key ? '(Me)' : ''}
         </div>
         <div>
           ${p.timestamp} - 
           ${new Date(p.timestamp)}<br>
       </div>
      </div>
-      ${p.body}
+      ${escapeHtml(p.body)}
+      <div class="post-reactions">
+        ${Object.entries(p.reactions).map(([reaction, count]) => `${escapeHtml(reaction)} ${count}`).join(' ')}
+        <button onclick="toggleLike('${p.id}', ${p.reacted_by_me})">${p.reacted_by_me ? 'Unlike' : 'Like'}</button>
+        ${identity.public_key === p.public_key ? `
+        <button onclick="editPost('${p.id}')">Edit</button>
+        <button onclick="deletePost('${p.id}')">Delete</button>` : ''}
+        ${p.edited ? '(edited)' : ''}
+      </div>
     </div>
     `
   })
   document.getElementById("post-list").innerHTML = postsString
+}
+
+async function renderMyPosts() {
+  const response = await fetch(`/profile/${identity.public_key}/feed?limit=10`)
+  const { feed } = await response.json()
+
+  document.getElementById("my-post-list").innerHTML = feed.posts.map(p => `
+    <div class="post">
+      ${new Date(p.timestamp * 1000).toLocaleString()}<br>
+      ${escapeHtml(p.body)}
+    </div>
+  `).join('')
+}
+
+(async () => {
+  console.log('ready')
+
+  document.getElementById("post-button").onclick = async () => {
+    const response = await postJson("/post", {
+      body: document.getElementById("post-text").value
+    })
+    if (response.ok) {
+      // show the post right away instead of waiting for the events stream
+      const { view } = await response.json()
+      posts.set(view.id, view)
+      renderPosts()
+      document.getElementById("post-text").value = ''
+    }
+  }
+
+  identity = await (await fetch("/id")).json()
+  console.log(identity)
+
+  const events = new EventSource("/events")
+  events.onmessage = (message) => {
+    const event = JSON.parse(message.data)
+    if (event.type === 'post') {
+      posts.set(event.post.id, event.post)
+      renderPosts()
+      if (event.post.public_key === identity.public_key) {
+        renderMyPosts()
+      }
+    } else if (event.type === 'profile') {
+      for (const p of posts.values()) {
+        if (p.public_key === event.profile.public_key) {
+          p.name = event.profile.name
+          p.avatar = event.profile.avatar
+        }
+      }
+      renderPosts()
+    } else {
+      console.log(event)
+    }
+  }
+
+  // the feed is paged newest first, older pages are loaded on demand
+  let next = null
+  const loadPosts = async () => {
+    const response = await fetch(next ? `/posts?before=${encodeURIComponent(next)}` : "/posts")
+    const page = await response.json()
+
+    page.posts.map(p => posts.set(p.id, p))
+    next = page.next
+    document.getElementById("load-more").style.display = next ? 'block' : 'none'
+    renderPosts()
+  }
+  document.getElementById("load-more").onclick = loadPosts
+  await loadPosts()
+  await renderMyPosts()
 
   Array.from(document.getElementsByClassName("avatar-here")).map(e => e.innerHTML = avatar(identity.public_key))
-})()
\ No newline at end of file
+})()
@@ -0,0 +1,245 @@
+// request and response types of the HTTP API, shared by the node and the command line client
+
+use p2panda_core::{Hash, PublicKey};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+
+/// File in a node's data directory holding the token its write endpoints require, sent as
+/// `Authorization: Bearer <token>`
+pub const TOKEN_FILE: &str = "api_token";
+
+/// Body of every failed request
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ErrorResponse {
+    pub error: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Identity {
+    pub public_key: PublicKey,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct PostBodyInput {
+    pub body: String,
+    #[serde(default)]
+    pub root: Option<Hash>,
+    #[serde(default)]
+    pub parent: Option<Hash>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ReactionInput {
+    pub target: Hash,
+    pub reaction: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct EditInput {
+    pub target: Hash,
+    pub new_body: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DeleteInput {
+    pub target: Hash,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct FollowInput {
+    pub public_key: PublicKey,
+}
+
+/// Profile fields to publish, fields left out keep their previous value
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AboutInput {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    /// base64 encoded image
+    pub avatar: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct InviteInput {
+    /// Follow whoever redeems the invite first
+    #[serde(default)]
+    pub follow_back: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct InviteCode {
+    pub code: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct MemberInput {
+    pub public_key: PublicKey,
+}
+
+/// Someone who redeemed one of our invites, a member of the pub when running as one
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Member {
+    pub profile: Profile,
+    pub following: bool,
+    /// Revoked members are blocked
+    pub revoked: bool,
+}
+
+/// Operation a write endpoint created together with what it materialized into
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CreatedOperation<T> {
+    pub hash: Hash,
+    pub seq_num: u64,
+    pub timestamp: u64,
+    pub public_key: PublicKey,
+    pub view: T,
+}
+
+/// Whether we follow a key after following or unfollowing it
+#[derive(Serialize, Deserialize, Debug)]
+pub struct FollowState {
+    pub target: PublicKey,
+    pub following: bool,
+}
+
+/// Whether we block a key after blocking or unblocking it
+#[derive(Serialize, Deserialize, Debug)]
+pub struct BlockState {
+    pub target: PublicKey,
+    pub blocked: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, sqlx::FromRow)]
+pub struct FrontendPost {
+    pub id: String,
+    pub public_key: String,
+    pub timestamp: i64,
+    pub body: String,
+    pub name: Option<String>,
+    pub avatar: Option<String>,
+    pub root: Option<String>,
+    pub parent: Option<String>,
+    pub edited: bool,
+    /// Deleted posts only show up as placeholders inside of threads
+    pub deleted: bool,
+    /// Number of authors per reaction
+    #[sqlx(skip)]
+    pub reactions: HashMap<String, u64>,
+    #[sqlx(skip)]
+    pub reacted_by_me: bool,
+}
+
+/// Query parameters of `/posts`, every filter is optional
+#[derive(Serialize, Deserialize, Debug, Default, rocket::FromForm)]
+pub struct PostsQuery {
+    /// Only posts older than this cursor, pass `next` of the previous page
+    pub before: Option<String>,
+    /// Posts per page, 50 by default and at most 500
+    pub limit: Option<u32>,
+    /// Only posts of this public key
+    pub author: Option<String>,
+    /// Only posts of people we follow
+    pub followed: Option<bool>,
+    /// Only posts of people within this many follows of us, including ourselves
+    pub hops: Option<u8>,
+    /// Unix timestamp in seconds, inclusive
+    pub since: Option<i64>,
+    /// Unix timestamp in seconds, exclusive
+    pub until: Option<i64>,
+    /// `true` for only replies, `false` for only posts starting a thread
+    pub replies: Option<bool>,
+}
+
+/// Posts newest first, `next` is set when there are older posts left
+#[derive(Serialize, Deserialize, Debug)]
+pub struct PostPage {
+    pub posts: Vec<FrontendPost>,
+    pub next: Option<String>,
+}
+
+/// An author's profile with a page of their posts
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ProfileFeed {
+    pub profile: Profile,
+    pub feed: PostPage,
+}
+
+#[derive(Serialize, Deserialize, Debug, sqlx::FromRow)]
+pub struct FrontendRevision {
+    pub id: String,
+    pub timestamp: i64,
+    pub body: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ThreadNode {
+    pub post: FrontendPost,
+    pub replies: Vec<ThreadNode>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Profile {
+    pub public_key: PublicKey,
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub avatar: Option<String>,
+}
+
+/// A key in the follow graph, `hops` and `path` are empty when we can't reach it through follows
+#[derive(Serialize, Deserialize, Debug)]
+pub struct GraphNode {
+    pub public_key: PublicKey,
+    pub hops: Option<u8>,
+    /// Chain of follows from us to this key, starting with our own key
+    pub path: Vec<PublicKey>,
+}
+
+/// How the node takes part in the network
+#[derive(Serialize, Deserialize, Debug)]
+pub struct NodeStatus {
+    pub public_key: PublicKey,
+    pub network_id: Hash,
+    /// Relay server we are connected to and reachable through, `None` while there is none
+    pub relay: Option<String>,
+    /// Relay server the node was started with
+    pub configured_relay: Option<String>,
+    pub direct_addresses: Vec<SocketAddr>,
+}
+
+/// Changes streamed to clients of the `/events` endpoint as they happen
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum LiveEvent {
+    /// A post arrived or changed through an edit, deletion or reaction
+    Post {
+        post: FrontendPost,
+    },
+    Follow {
+        public_key: PublicKey,
+        target: PublicKey,
+        following: bool,
+    },
+    Profile {
+        profile: Profile,
+    },
+    Peer {
+        public_key: Option<PublicKey>,
+        status: PeerStatus,
+    },
+    /// Someone redeemed one of our invites
+    InviteRedeemed {
+        public_key: PublicKey,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum PeerStatus {
+    /// We met a first peer and can gossip with the network
+    GossipReady,
+    /// A peer sent us an operation over gossip
+    Gossiped,
+    /// A peer sent us an operation while syncing
+    Synced,
+}
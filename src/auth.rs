@@ -0,0 +1,138 @@
+// API token and origin checks guarding the write routes
+
+use anyhow::{Context, Result};
+use rocket::http::Status;
+use rocket::request::{FromRequest, Outcome};
+use rocket::Request;
+use std::fs;
+use std::io::Write;
+use std::net::IpAddr;
+use std::path::Path;
+
+use crate::config::Config;
+use panda_butt::api::TOKEN_FILE;
+
+/// Secret every write to the HTTP API has to present, and the origins browsers may send writes
+/// from. Without these any web page the user visits could publish under their identity.
+pub struct ApiAuth {
+    token: String,
+    origins: Vec<String>,
+    cookie: String,
+}
+
+impl ApiAuth {
+    pub fn new(token: String, config: &Config) -> Self {
+        let host = match config.address {
+            IpAddr::V4(address) => address.to_string(),
+            IpAddr::V6(address) => format!("[{}]", address),
+        };
+        let mut origins = vec![
+            format!("http://{}:{}", host, config.port),
+            format!("http://localhost:{}", config.port),
+            format!("http://127.0.0.1:{}", config.port),
+        ];
+        origins.extend(config.allowed_origins.iter().cloned());
+
+        ApiAuth {
+            token,
+            origins,
+            // Cookies don't tell ports apart, so nodes on the same machine need their own
+            cookie: format!("pandabutt_token_{}", config.port),
+        }
+    }
+
+    pub fn token(&self) -> &str {
+        &self.token
+    }
+
+    pub fn is_valid(&self, token: &str) -> bool {
+        tokens_match(token, &self.token)
+    }
+
+    /// Cookie the web UI receives the token in
+    pub fn cookie(&self) -> &str {
+        &self.cookie
+    }
+}
+
+/// Read the API token of a data directory, creating it on first use
+pub fn get_token(dir: &Path) -> Result<String> {
+    let path = dir.join(TOKEN_FILE);
+    if let Ok(token) = fs::read_to_string(&path) {
+        return Ok(token.trim().to_string());
+    }
+
+    let token: String = rand::random::<[u8; 32]>()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options
+        .open(&path)
+        .and_then(|mut file| file.write_all(token.as_bytes()))
+        .with_context(|| format!("could not write API token to {}", path.display()))?;
+    Ok(token)
+}
+
+/// Request guard of every write route. Requests need the API token, either as a bearer token
+/// or in the cookie handed to the web UI, and browsers must send them from one of our origins.
+pub struct Authorized;
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Authorized {
+    type Error = &'static str;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let Some(auth) = request.rocket().state::<ApiAuth>() else {
+            return Outcome::Error((Status::InternalServerError, "API token not loaded"));
+        };
+
+        // Browsers always send an origin with cross-origin writes, other clients don't have to
+        if let Some(origin) = request.headers().get_one("Origin") {
+            if !auth.origins.iter().any(|allowed| allowed == origin) {
+                return Outcome::Error((Status::Forbidden, "origin not allowed"));
+            }
+        }
+
+        let bearer = request
+            .headers()
+            .get_one("Authorization")
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .map(|token| token.to_string());
+        let cookie = request
+            .cookies()
+            .get(&auth.cookie)
+            .map(|cookie| cookie.value().to_string());
+
+        match bearer.or(cookie) {
+            Some(token) if auth.is_valid(&token) => Outcome::Success(Authorized),
+            _ => Outcome::Error((Status::Unauthorized, "missing or invalid API token")),
+        }
+    }
+}
+
+/// Compare without returning early so response times don't leak how much of a guess was right
+fn tokens_match(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0, |difference, (a, b)| difference | (a ^ b))
+            == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compares_tokens() {
+        assert!(tokens_match("c0ffee", "c0ffee"));
+        assert!(!tokens_match("c0ffee", "c0ffef"));
+        assert!(!tokens_match("c0ffee", "c0ffee00"));
+        assert!(!tokens_match("", "c0ffee"));
+    }
+}
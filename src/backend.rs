@@ -1,21 +1,33 @@
+use crate::config::Config;
+use crate::error::InvalidInput;
+use crate::invite::{redemption_proof, Invite};
 use crate::node::ButtNode;
-use crate::operation::{ButtEvent, ButtExtensions};
+use crate::operation::{ButtAbout, ButtEvent, ButtExtensions, ButtPostContent};
+use crate::peers::PeerAddress;
 use crate::topic;
 use crate::utils::CombinedMigrationSource;
 
 use anyhow::Result;
-use p2panda_core::PublicKey;
-use p2panda_core::{Body, Header, PrivateKey};
+use p2panda_core::{Body, Header, PrivateKey, PruneFlag};
+use p2panda_core::{Hash, PublicKey};
 use p2panda_store::sqlite::store::{
     connection_pool, create_database, migrations as operation_store_migrations, Pool,
 };
 use p2panda_store::{LocalOperationStore, LogStore, SqliteStore};
+use panda_butt::api::{
+    FrontendPost, FrontendRevision, GraphNode, LiveEvent, Member, NodeStatus, PostPage, PostsQuery,
+    Profile, ProfileFeed, ThreadNode,
+};
 use serde::{Deserialize, Serialize};
 use sqlx::migrate::Migrator;
-use sqlx::sqlite::SqliteRow;
-use sqlx::Row;
+use sqlx::{QueryBuilder, Sqlite};
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
 use std::hash::Hash as StdHash;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
 use std::time::SystemTime;
+use tokio::sync::broadcast;
 use tokio::sync::mpsc::{self};
 
 #[derive(Serialize, Deserialize, Clone, Debug, Copy, Eq, PartialEq, StdHash)]
@@ -23,31 +35,210 @@ pub struct ButtLogId(pub PublicKey);
 
 pub type OperationStore = SqliteStore<ButtLogId, ButtExtensions>;
 
+/// Bump whenever materialization changes in a way that needs existing projections rebuilt, the
+/// app tables are reindexed from the operation store on the next start
+const PROJECTION_VERSION: &str = "1";
+
+/// Every table `AppData` materializes into, children before the posts they reference
+const APP_TABLES: [&str; 9] = [
+    "replies",
+    "post_revisions",
+    "posts",
+    "follows",
+    "blocks",
+    "reactions",
+    "profiles",
+    "unknown_events",
+    "quarantine",
+];
+
 #[derive(Clone, Debug)]
 pub struct AppData {
     pub pool: sqlx::SqlitePool,
+    public_key: PublicKey,
+    /// Keys blocked by us, kept in memory so the network ingest stream can check it cheaply
+    blocked: Arc<RwLock<HashSet<PublicKey>>>,
+    events: broadcast::Sender<LiveEvent>,
 }
 
 impl AppData {
-    async fn new(connection_pool: Pool) -> Self {
-        AppData {
+    async fn new(connection_pool: Pool, public_key: PublicKey) -> Self {
+        let app_data = AppData {
             pool: connection_pool,
+            public_key,
+            blocked: Arc::new(RwLock::new(HashSet::new())),
+            events: broadcast::channel(256).0,
+        };
+        app_data.refresh_blocked().await;
+        app_data
+    }
+
+    /// Receive every change to the materialized views from now on
+    pub fn subscribe(&self) -> broadcast::Receiver<LiveEvent> {
+        self.events.subscribe()
+    }
+
+    /// Send an event to all live subscribers, it's fine if there are none
+    pub fn publish(&self, event: LiveEvent) {
+        let _ = self.events.send(event);
+    }
+
+    /// Tell live subscribers what materializing an event changed
+    async fn announce(&self, event: &ButtEvent, header: &Header<ButtExtensions>) {
+        if self.events.receiver_count() == 0 {
+            return;
+        }
+
+        let live_event = match event {
+            ButtEvent::Post(_) => self
+                .get_post(&header.hash())
+                .await
+                .map(|post| LiveEvent::Post { post }),
+            ButtEvent::Edit { target, .. }
+            | ButtEvent::Delete { target }
+            | ButtEvent::React { target, .. }
+            | ButtEvent::Unreact { target, .. } => self
+                .get_post(target)
+                .await
+                .map(|post| LiveEvent::Post { post }),
+            ButtEvent::Follow(target) | ButtEvent::Unfollow(target) => Some(LiveEvent::Follow {
+                public_key: header.public_key,
+                target: *target,
+                following: matches!(event, ButtEvent::Follow(_)),
+            }),
+            ButtEvent::About(_) => Some(LiveEvent::Profile {
+                profile: self.get_profile(&header.public_key).await,
+            }),
+            ButtEvent::Block(_)
+            | ButtEvent::Unblock(_)
+            | ButtEvent::RedeemInvite { .. }
+            | ButtEvent::Unknown { .. } => None,
+        };
+
+        if let Some(live_event) = live_event {
+            self.publish(live_event);
         }
     }
 
-    pub async fn materialize(&self, event: &ButtEvent, header: &Header<ButtExtensions>) {
+    pub async fn materialize(
+        &self,
+        event: &ButtEvent,
+        header: &Header<ButtExtensions>,
+    ) -> Result<()> {
         println!("Materializing event");
         match event {
-            ButtEvent::Follow(_) => {
-                // app_data
-                //     .friends
-                //     .entry(*friend_key)
-                //     .and_modify(|public_keys| {
-                //         public_keys.insert(*friend_key);
-                //     });
+            ButtEvent::Follow(target) => {
+                self.set_link("follows", header, target, true).await?;
+            }
+            ButtEvent::Unfollow(target) => {
+                self.set_link("follows", header, target, false).await?;
+            }
+            ButtEvent::Block(target) => {
+                self.set_link("blocks", header, target, true).await?;
+                if header.public_key == self.public_key {
+                    self.refresh_blocked().await;
+                }
+            }
+            ButtEvent::Unblock(target) => {
+                self.set_link("blocks", header, target, false).await?;
+                if header.public_key == self.public_key {
+                    self.refresh_blocked().await;
+                }
+            }
+            ButtEvent::About(about) => {
+                let fields = [
+                    ("name", &about.name),
+                    ("description", &about.description),
+                    ("avatar", &about.avatar),
+                ];
+                for (field, value) in fields {
+                    if let Some(value) = value {
+                        self.set_profile_field(header, field, value).await?;
+                    }
+                }
+            }
+            ButtEvent::React { target, reaction } => {
+                self.set_reaction(header, target, reaction, true).await?;
+            }
+            ButtEvent::Unreact { target, reaction } => {
+                self.set_reaction(header, target, reaction, false).await?;
+            }
+            ButtEvent::Edit { target, new_body } => {
+                sqlx::query(
+                    "
+                    INSERT OR IGNORE INTO post_revisions
+                        ( id, target, public_key, timestamp, sequence, body )
+                    VALUES ( ?, ?, ?, ?, ?, ? )
+                    ",
+                )
+                .bind(header.hash().to_string())
+                .bind(target.to_string())
+                .bind(header.public_key.to_string())
+                .bind(header.timestamp as i64)
+                .bind(header.seq_num as i64)
+                .bind(new_body)
+                .execute(&self.pool)
+                .await?;
+
+                // Only the author can edit their post, the newest revision is what we show
+                sqlx::query(
+                    "
+                    UPDATE posts SET body = (
+                        SELECT body FROM post_revisions
+                        WHERE target = posts.id AND public_key = posts.public_key
+                        ORDER BY sequence DESC LIMIT 1
+                    )
+                    WHERE id = ? AND public_key = ? AND deleted = FALSE
+                    ",
+                )
+                .bind(target.to_string())
+                .bind(header.public_key.to_string())
+                .execute(&self.pool)
+                .await?;
+            }
+            ButtEvent::Delete { target } => {
+                sqlx::query(
+                    "UPDATE posts SET body = '', deleted = TRUE WHERE id = ? AND public_key = ?",
+                )
+                .bind(target.to_string())
+                .bind(header.public_key.to_string())
+                .execute(&self.pool)
+                .await?;
+
+                sqlx::query(
+                    "UPDATE post_revisions SET body = NULL WHERE target = ? AND public_key = ?",
+                )
+                .bind(target.to_string())
+                .bind(header.public_key.to_string())
+                .execute(&self.pool)
+                .await?;
+            }
+            ButtEvent::RedeemInvite { inviter, proof } if *inviter == self.public_key => {
+                match self.find_redeemed_invite(proof, &header.public_key).await {
+                    Some(invite) => self.add_redemption(&invite, &header.public_key).await?,
+                    None => println!(
+                        "ignoring redemption without a valid proof by {}",
+                        header.public_key
+                    ),
+                }
+            }
+            ButtEvent::RedeemInvite { .. } => (),
+            ButtEvent::Unknown { event_type, raw } => {
+                sqlx::query(
+                    "
+                    INSERT OR IGNORE INTO unknown_events ( id, public_key, event_type, raw )
+                    VALUES ( ?, ?, ?, ? )
+                    ",
+                )
+                .bind(header.hash().to_string())
+                .bind(header.public_key.to_string())
+                .bind(event_type)
+                .bind(raw)
+                .execute(&self.pool)
+                .await?;
             }
-            ButtEvent::Post(body) => {
-                let _result = sqlx::query(
+            ButtEvent::Post(post) => {
+                sqlx::query(
                     "
                     INSERT OR IGNORE INTO posts ( id, public_key, timestamp, body )
                     VALUES ( ?, ?, ?, ? )
@@ -55,10 +246,40 @@ impl AppData {
                 )
                 .bind(header.hash().to_string())
                 .bind(header.public_key.to_string())
-                .bind(header.timestamp.to_string())
-                .bind(body)
+                .bind(header.timestamp as i64)
+                .bind(&post.body)
                 .execute(&self.pool)
-                .await;
+                .await?;
+
+                // Replies are indexed even when the root has not been replicated yet, the thread
+                // links up as soon as it arrives. Replies naming only their parent belong to the
+                // parent's thread.
+                let root = match (post.root, post.parent) {
+                    (Some(root), _) => Some(root),
+                    (None, Some(parent)) => Some(self.get_thread_root(&parent).await),
+                    (None, None) => None,
+                };
+                if let Some(root) = root {
+                    sqlx::query(
+                        "
+                        INSERT OR IGNORE INTO replies ( id, root, parent )
+                        VALUES ( ?, ?, ? )
+                        ",
+                    )
+                    .bind(header.hash().to_string())
+                    .bind(root.to_string())
+                    .bind(post.parent.unwrap_or(root).to_string())
+                    .execute(&self.pool)
+                    .await?;
+
+                    // Replies which arrived before us and only knew us as their parent were
+                    // filed under our id, move them into our thread
+                    sqlx::query("UPDATE replies SET root = ? WHERE root = ?")
+                        .bind(root.to_string())
+                        .bind(header.hash().to_string())
+                        .execute(&self.pool)
+                        .await?;
+                }
                 // let post = ButtPost {
                 //     body: body.clone(),
                 //     public_key: header.public_key,
@@ -76,68 +297,918 @@ impl AppData {
                 // drop(app_data);
             }
         }
+
+        self.announce(event, header).await;
         // self.save().await;
+        Ok(())
+    }
+
+    /// Placeholder for a deleted post, does nothing if the post was materialized before
+    async fn add_tombstone(&self, header: &Header<ButtExtensions>) -> Result<()> {
+        sqlx::query(
+            "
+            INSERT OR IGNORE INTO posts ( id, public_key, timestamp, body, deleted )
+            VALUES ( ?, ?, ?, '', TRUE )
+            ",
+        )
+        .bind(header.hash().to_string())
+        .bind(header.public_key.to_string())
+        .bind(header.timestamp as i64)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Empty all materialized views, ready for them to be rebuilt from the operation store
+    pub async fn truncate(&self) -> Result<()> {
+        for table in APP_TABLES {
+            sqlx::query(&format!("DELETE FROM {table}"))
+                .execute(&self.pool)
+                .await?;
+        }
+        self.refresh_blocked().await;
+        Ok(())
+    }
+
+    async fn projection_version(&self) -> Option<String> {
+        sqlx::query_scalar("SELECT value FROM app_meta WHERE key = 'projection_version'")
+            .fetch_optional(&self.pool)
+            .await
+            .unwrap_or(None)
     }
 
-    pub async fn get_posts(&self) -> Vec<FrontendPost> {
-        let posts: Vec<SqliteRow> =
-            sqlx::query("SELECT id, public_key, timestamp, body FROM posts")
+    async fn set_projection_version(&self, version: &str) -> Result<()> {
+        sqlx::query(
+            "INSERT OR REPLACE INTO app_meta ( key, value ) VALUES ( 'projection_version', ? )",
+        )
+        .bind(version)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Keep a body we could not decode around together with the reason, so it can be looked at
+    /// or retried later instead of taking down the materializer
+    pub async fn quarantine(
+        &self,
+        header: &Header<ButtExtensions>,
+        body: &Body,
+        reason: &str,
+    ) -> Result<()> {
+        println!("Quarantining operation {}: {}", header.hash(), reason);
+        sqlx::query(
+            "
+            INSERT OR REPLACE INTO quarantine ( id, public_key, sequence, reason, raw )
+            VALUES ( ?, ?, ?, ?, ? )
+            ",
+        )
+        .bind(header.hash().to_string())
+        .bind(header.public_key.to_string())
+        .bind(header.seq_num as i64)
+        .bind(reason)
+        .bind(body.to_bytes())
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Record a follow or block state between the author and a target in `table`, the latest
+    /// seq_num wins
+    async fn set_link(
+        &self,
+        table: &str,
+        header: &Header<ButtExtensions>,
+        target: &PublicKey,
+        state: bool,
+    ) -> Result<()> {
+        let query = format!(
+            "
+            INSERT INTO {table} ( public_key, target, state, sequence )
+            VALUES ( ?, ?, ?, ? )
+            ON CONFLICT ( public_key, target ) DO UPDATE
+            SET state = excluded.state, sequence = excluded.sequence
+            WHERE excluded.sequence > {table}.sequence
+            "
+        );
+        sqlx::query(&query)
+            .bind(header.public_key.to_string())
+            .bind(target.to_string())
+            .bind(state)
+            .bind(header.seq_num as i64)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Reactions are toggled per author, target and reaction, the latest seq_num wins
+    async fn set_reaction(
+        &self,
+        header: &Header<ButtExtensions>,
+        target: &Hash,
+        reaction: &str,
+        state: bool,
+    ) -> Result<()> {
+        sqlx::query(
+            "
+            INSERT INTO reactions ( public_key, target, reaction, state, sequence )
+            VALUES ( ?, ?, ?, ?, ? )
+            ON CONFLICT ( public_key, target, reaction ) DO UPDATE
+            SET state = excluded.state, sequence = excluded.sequence
+            WHERE excluded.sequence > reactions.sequence
+            ",
+        )
+        .bind(header.public_key.to_string())
+        .bind(target.to_string())
+        .bind(reaction)
+        .bind(state)
+        .bind(header.seq_num as i64)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn refresh_blocked(&self) {
+        let rows: Vec<String> =
+            sqlx::query_scalar("SELECT target FROM blocks WHERE public_key = ? AND state = TRUE")
+                .bind(self.public_key.to_string())
                 .fetch_all(&self.pool)
                 .await
                 .unwrap_or(vec![]);
+        let blocked = parse_keys(rows);
+        *self.blocked.write().expect("blocked lock not poisoned") = blocked.into_iter().collect();
+    }
 
-        posts
+    /// Every key we have blocked
+    pub fn get_blocked(&self) -> Vec<PublicKey> {
+        self.blocked
+            .read()
+            .expect("blocked lock not poisoned")
             .iter()
-            .filter_map(|row| {
-                let Ok(id) = row.try_get::<String, _>("id") else {
-                    return None;
-                };
-                let Ok(public_key) = row.try_get::<String, _>("public_key") else {
-                    return None;
-                };
-                let Ok(timestamp) = row.try_get::<u64, _>("timestamp") else {
-                    return None;
-                };
-                let Ok(body) = row.try_get::<String, _>("body") else {
-                    return None;
-                };
-                Some(FrontendPost {
-                    id,
+            .copied()
+            .collect()
+    }
+
+    /// Whether we have blocked this key
+    pub fn is_blocked(&self, public_key: &PublicKey) -> bool {
+        self.blocked
+            .read()
+            .expect("blocked lock not poisoned")
+            .contains(public_key)
+    }
+
+    /// Each profile field is last-write-wins on its own, so an about event which only sets a name
+    /// does not roll back a newer avatar
+    async fn set_profile_field(
+        &self,
+        header: &Header<ButtExtensions>,
+        field: &str,
+        value: &str,
+    ) -> Result<()> {
+        let query = format!(
+            "
+            INSERT INTO profiles ( public_key, {field}, {field}_sequence )
+            VALUES ( ?, ?, ? )
+            ON CONFLICT ( public_key ) DO UPDATE
+            SET {field} = excluded.{field}, {field}_sequence = excluded.{field}_sequence
+            WHERE profiles.{field}_sequence IS NULL
+            OR excluded.{field}_sequence > profiles.{field}_sequence
+            "
+        );
+        sqlx::query(&query)
+            .bind(header.public_key.to_string())
+            .bind(value)
+            .bind(header.seq_num as i64)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Latest profile fields an author published
+    pub async fn get_about(&self, public_key: &PublicKey) -> ButtAbout {
+        let row: Option<(Option<String>, Option<String>, Option<String>)> =
+            sqlx::query_as("SELECT name, description, avatar FROM profiles WHERE public_key = ?")
+                .bind(public_key.to_string())
+                .fetch_optional(&self.pool)
+                .await
+                .unwrap_or(None);
+
+        let Some((name, description, avatar)) = row else {
+            return ButtAbout::default();
+        };
+        ButtAbout {
+            name,
+            description,
+            avatar,
+        }
+    }
+
+    pub async fn get_profile(&self, public_key: &PublicKey) -> Profile {
+        let about = self.get_about(public_key).await;
+        Profile {
+            public_key: *public_key,
+            name: about.name,
+            description: about.description,
+            avatar: about.avatar,
+        }
+    }
+
+    /// Keys which the given key currently follows
+    pub async fn get_following(&self, public_key: &PublicKey) -> Vec<PublicKey> {
+        let rows: Vec<String> =
+            sqlx::query_scalar("SELECT target FROM follows WHERE public_key = ? AND state = TRUE")
+                .bind(public_key.to_string())
+                .fetch_all(&self.pool)
+                .await
+                .unwrap_or(vec![]);
+        parse_keys(rows)
+    }
+
+    /// Keys which currently follow the given key
+    pub async fn get_followers(&self, public_key: &PublicKey) -> Vec<PublicKey> {
+        let rows: Vec<String> =
+            sqlx::query_scalar("SELECT public_key FROM follows WHERE target = ? AND state = TRUE")
+                .bind(public_key.to_string())
+                .fetch_all(&self.pool)
+                .await
+                .unwrap_or(vec![]);
+        parse_keys(rows)
+    }
+
+    /// Every key reached within `hops` of `root` together with its distance, see
+    /// `get_follow_paths`
+    pub async fn get_hop_distances(&self, root: &PublicKey, hops: u8) -> HashMap<PublicKey, u8> {
+        self.get_follow_paths(root, hops)
+            .await
+            .into_iter()
+            .map(|(public_key, path)| (public_key, (path.len() - 1) as u8))
+            .collect()
+    }
+
+    /// Walk the follow graph outwards from `root`, returning every key reached within `hops`
+    /// together with a shortest chain of follows leading to it, starting with `root`. The root
+    /// itself is included with a path of only itself, keys we blocked are never reached.
+    pub async fn get_follow_paths(
+        &self,
+        root: &PublicKey,
+        hops: u8,
+    ) -> HashMap<PublicKey, Vec<PublicKey>> {
+        let mut paths = HashMap::from([(*root, vec![*root])]);
+        let mut frontier = vec![*root];
+
+        for _ in 1..=hops {
+            let mut next_frontier = vec![];
+            for public_key in frontier {
+                for target in self.get_following(&public_key).await {
+                    if self.is_blocked(&target) {
+                        continue;
+                    }
+                    if let Entry::Vacant(entry) = paths.entry(target) {
+                        let mut path = paths[&public_key].clone();
+                        path.push(target);
+                        entry.insert(path);
+                        next_frontier.push(target);
+                    }
+                }
+            }
+            if next_frontier.is_empty() {
+                break;
+            }
+            frontier = next_frontier;
+        }
+
+        paths
+    }
+
+    /// Every key we know of with its distance from us in the follow graph
+    pub async fn get_graph(&self) -> Vec<GraphNode> {
+        let mut paths = self.get_follow_paths(&self.public_key, u8::MAX).await;
+        let mut nodes: Vec<GraphNode> = self
+            .get_known_keys()
+            .await
+            .into_iter()
+            .chain(paths.keys().copied().collect::<Vec<_>>())
+            .collect::<HashSet<PublicKey>>()
+            .into_iter()
+            .map(|public_key| {
+                let path = paths.remove(&public_key).unwrap_or_default();
+                GraphNode {
                     public_key,
-                    timestamp,
-                    body,
-                })
+                    hops: path.len().checked_sub(1).map(|hops| hops as u8),
+                    path,
+                }
             })
-            .collect()
+            .collect();
+        nodes.sort_by_key(|node| (node.hops.is_none(), node.hops, node.public_key.to_string()));
+        nodes
+    }
+
+    /// One page of posts matching the query, newest first
+    pub async fn query_posts(&self, query: &PostsQuery) -> Result<PostPage> {
+        self.fetch_posts(query, None).await
+    }
+
+    /// Posts of everyone we follow and our own
+    pub async fn get_home_feed(&self, query: &PostsQuery) -> Result<PostPage> {
+        let mut authors = self.get_following(&self.public_key).await;
+        authors.push(self.public_key);
+        self.fetch_posts(query, Some(&authors)).await
     }
 
-    pub async fn get_all_keys(&self) -> Vec<PublicKey> {
-        let unique_keys: Vec<SqliteRow> = sqlx::query("SELECT DISTINCT public_key FROM posts")
+    /// Profile of an author together with a page of their posts
+    pub async fn get_profile_feed(
+        &self,
+        public_key: &PublicKey,
+        query: &PostsQuery,
+    ) -> Result<ProfileFeed> {
+        Ok(ProfileFeed {
+            profile: self.get_profile(public_key).await,
+            feed: self.fetch_posts(query, Some(&[*public_key])).await?,
+        })
+    }
+
+    /// Pages are keyed by a cursor of timestamp and hash so posts arriving in the meantime don't
+    /// shift them around. `authors` restricts the posts further than the query does.
+    async fn fetch_posts(
+        &self,
+        query: &PostsQuery,
+        authors: Option<&[PublicKey]>,
+    ) -> Result<PostPage> {
+        let limit = query
+            .limit
+            .unwrap_or(DEFAULT_PAGE_SIZE)
+            .clamp(1, MAX_PAGE_SIZE);
+
+        let mut builder = QueryBuilder::<Sqlite>::new(POST_SELECT);
+        builder
+            .push(" WHERE posts.deleted = FALSE")
+            .push(" AND posts.public_key NOT IN (SELECT target FROM blocks WHERE public_key = ")
+            .push_bind(self.public_key.to_string())
+            .push(" AND state = TRUE)");
+
+        if let Some(authors) = authors {
+            let keys: Vec<String> = authors.iter().map(|key| key.to_string()).collect();
+            builder
+                .push(" AND posts.public_key IN (SELECT value FROM json_each(")
+                .push_bind(serde_json::to_string(&keys).expect("keys converted to json"))
+                .push("))");
+        }
+        if let Some(author) = &query.author {
+            let author: PublicKey = author
+                .parse()
+                .map_err(|_| InvalidInput("invalid author".to_string()))?;
+            builder
+                .push(" AND posts.public_key = ")
+                .push_bind(author.to_string());
+        }
+        if query.followed == Some(true) {
+            builder
+                .push(" AND posts.public_key IN (SELECT target FROM follows WHERE public_key = ")
+                .push_bind(self.public_key.to_string())
+                .push(" AND state = TRUE)");
+        }
+        if let Some(hops) = query.hops {
+            let keys: Vec<String> = self
+                .get_hop_distances(&self.public_key, hops)
+                .await
+                .into_keys()
+                .map(|public_key| public_key.to_string())
+                .collect();
+            builder
+                .push(" AND posts.public_key IN (SELECT value FROM json_each(")
+                .push_bind(serde_json::to_string(&keys).expect("keys converted to json"))
+                .push("))");
+        }
+        if let Some(since) = query.since {
+            builder.push(" AND posts.timestamp >= ").push_bind(since);
+        }
+        if let Some(until) = query.until {
+            builder.push(" AND posts.timestamp < ").push_bind(until);
+        }
+        match query.replies {
+            Some(true) => {
+                builder.push(" AND replies.id IS NOT NULL");
+            }
+            Some(false) => {
+                builder.push(" AND replies.id IS NULL");
+            }
+            None => (),
+        }
+        if let Some(before) = &query.before {
+            let (timestamp, id) =
+                parse_cursor(before).ok_or_else(|| InvalidInput("invalid cursor".to_string()))?;
+            builder
+                .push(" AND (posts.timestamp < ")
+                .push_bind(timestamp)
+                .push(" OR (posts.timestamp = ")
+                .push_bind(timestamp)
+                .push(" AND posts.id < ")
+                .push_bind(id)
+                .push("))");
+        }
+
+        // Fetch one more than asked for to know whether there is another page
+        builder
+            .push(" ORDER BY posts.timestamp DESC, posts.id DESC LIMIT ")
+            .push_bind(limit + 1);
+
+        let mut posts: Vec<FrontendPost> = builder.build_query_as().fetch_all(&self.pool).await?;
+
+        let next = if posts.len() > limit as usize {
+            posts.truncate(limit as usize);
+            posts
+                .last()
+                .map(|post| format!("{}:{}", post.timestamp, post.id))
+        } else {
+            None
+        };
+
+        self.add_reactions(&mut posts).await;
+        Ok(PostPage { posts, next })
+    }
+
+    /// A single post including deleted ones, unless its author is blocked
+    pub async fn get_post(&self, id: &Hash) -> Option<FrontendPost> {
+        let query = format!(
+            "
+            {POST_SELECT}
+            WHERE posts.id = ?
+            AND posts.public_key NOT IN ({BLOCKED_KEYS})
+            "
+        );
+        let post: FrontendPost = sqlx::query_as(&query)
+            .bind(id.to_string())
+            .bind(self.public_key.to_string())
+            .fetch_optional(&self.pool)
+            .await
+            .unwrap_or(None)?;
+
+        let mut posts = [post];
+        self.add_reactions(&mut posts).await;
+        let [post] = posts;
+        Some(post)
+    }
+
+    /// Fill in reaction counts for the given posts, ignoring reactions of blocked keys
+    async fn add_reactions(&self, posts: &mut [FrontendPost]) {
+        let ids: Vec<&String> = posts.iter().map(|post| &post.id).collect();
+        let query = format!(
+            "
+            SELECT target, reaction, COUNT(*) AS count, MAX(public_key = ?) AS mine
+            FROM reactions
+            WHERE state = TRUE
+            AND target IN (SELECT value FROM json_each(?))
+            AND public_key NOT IN ({BLOCKED_KEYS})
+            GROUP BY target, reaction
+            "
+        );
+        let rows: Vec<ReactionCount> = sqlx::query_as(&query)
+            .bind(self.public_key.to_string())
+            .bind(serde_json::to_string(&ids).expect("ids converted to json"))
+            .bind(self.public_key.to_string())
             .fetch_all(&self.pool)
             .await
             .unwrap_or(vec![]);
-        unique_keys
-            .iter()
-            .filter_map(|row| {
-                let Ok(public_key) = row.try_get::<&[u8], _>("public_key") else {
-                    return None;
-                };
-                PublicKey::try_from(public_key).ok()
-            })
-            .collect()
+
+        let mut reactions: HashMap<String, Vec<ReactionCount>> = HashMap::new();
+        for row in rows {
+            reactions.entry(row.target.clone()).or_default().push(row);
+        }
+
+        for post in posts.iter_mut() {
+            for row in reactions.remove(&post.id).unwrap_or_default() {
+                post.reactions.insert(row.reaction, row.count as u64);
+                post.reacted_by_me |= row.mine;
+            }
+        }
+    }
+
+    /// Every edit the author made to a post, oldest first
+    pub async fn get_revisions(&self, target: &Hash) -> Vec<FrontendRevision> {
+        sqlx::query_as(
+            "
+            SELECT post_revisions.id, post_revisions.timestamp, post_revisions.body
+            FROM post_revisions
+            JOIN posts ON posts.id = post_revisions.target
+                AND posts.public_key = post_revisions.public_key
+            WHERE post_revisions.target = ? AND post_revisions.body IS NOT NULL
+            ORDER BY post_revisions.sequence
+            ",
+        )
+        .bind(target.to_string())
+        .fetch_all(&self.pool)
+        .await
+        .unwrap_or(vec![])
+    }
+
+    /// Operations holding the text of a post, the post itself and all of its edits
+    async fn get_post_operation_ids(&self, target: &Hash) -> Vec<Hash> {
+        let rows: Vec<String> =
+            sqlx::query_scalar("SELECT id FROM post_revisions WHERE target = ?")
+                .bind(target.to_string())
+                .fetch_all(&self.pool)
+                .await
+                .unwrap_or(vec![]);
+
+        let mut hashes = vec![*target];
+        hashes.extend(rows.iter().filter_map(|id| id.parse::<Hash>().ok()));
+        hashes
+    }
+
+    /// The root post with all of its replies nested under their parents. Replies whose parent
+    /// we don't have are attached to the root directly. Returns `None` until the root arrived.
+    pub async fn get_thread(&self, root: &Hash) -> Option<ThreadNode> {
+        let query = format!(
+            "
+            {POST_SELECT}
+            WHERE (posts.id = ? OR replies.root = ?)
+            AND posts.public_key NOT IN ({BLOCKED_KEYS})
+            ORDER BY posts.timestamp
+            "
+        );
+        let mut posts: Vec<FrontendPost> = sqlx::query_as(&query)
+            .bind(root.to_string())
+            .bind(root.to_string())
+            .bind(self.public_key.to_string())
+            .fetch_all(&self.pool)
+            .await
+            .unwrap_or(vec![]);
+        self.add_reactions(&mut posts).await;
+
+        let root_id = root.to_string();
+        let ids: HashSet<String> = posts.iter().map(|post| post.id.clone()).collect();
+        let mut root_post = None;
+        let mut children: HashMap<String, Vec<FrontendPost>> = HashMap::new();
+        for post in posts {
+            if post.id == root_id {
+                root_post = Some(post);
+                continue;
+            }
+            let parent = match &post.parent {
+                Some(parent) if ids.contains(parent) => parent.clone(),
+                _ => root_id.clone(),
+            };
+            children.entry(parent).or_default().push(post);
+        }
+
+        root_post.map(|post| build_thread(post, &mut children))
+    }
+
+    /// Root of the thread a post belongs to, which is the post itself when it is no reply
+    pub async fn get_thread_root(&self, id: &Hash) -> Hash {
+        let root: Option<String> = sqlx::query_scalar("SELECT root FROM replies WHERE id = ?")
+            .bind(id.to_string())
+            .fetch_optional(&self.pool)
+            .await
+            .unwrap_or(None);
+        root.and_then(|root| root.parse().ok()).unwrap_or(*id)
+    }
+
+    /// Our invite the redeemer proved to hold, if any
+    async fn find_redeemed_invite(&self, proof: &Hash, redeemer: &PublicKey) -> Option<Hash> {
+        let ids: Vec<String> = sqlx::query_scalar("SELECT id FROM invites")
+            .fetch_all(&self.pool)
+            .await
+            .unwrap_or(vec![]);
+        ids.iter()
+            .filter_map(|id| id.parse::<Hash>().ok())
+            .find(|id| redemption_proof(id, redeemer) == *proof)
+    }
+
+    /// Record that someone redeemed one of our invites, redemptions beyond its uses don't count
+    async fn add_redemption(&self, invite: &Hash, redeemer: &PublicKey) -> Result<()> {
+        let result = sqlx::query(
+            "
+            INSERT INTO invite_redemptions ( invite, public_key )
+            SELECT id, ? FROM invites
+            WHERE id = ? AND (
+                uses IS NULL
+                OR uses > (SELECT COUNT(*) FROM invite_redemptions WHERE invite = invites.id)
+            )
+            ON CONFLICT DO NOTHING
+            ",
+        )
+        .bind(redeemer.to_string())
+        .bind(invite.to_string())
+        .execute(&self.pool)
+        .await?;
+
+        if result.rows_affected() == 1 {
+            self.publish(LiveEvent::InviteRedeemed {
+                public_key: *redeemer,
+            });
+        }
+        Ok(())
+    }
+
+    /// Remember an invite we handed out so we recognize its redemptions, `uses` is unlimited
+    /// when `None`
+    pub async fn add_invite(&self, invite: &Invite, uses: Option<u32>) -> Result<()> {
+        let created = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .expect("time from operation system")
+            .as_secs();
+        sqlx::query("INSERT INTO invites ( id, created, follow_back, uses ) VALUES ( ?, ?, ?, ? )")
+            .bind(invite.id.to_string())
+            .bind(created as i64)
+            .bind(invite.follow_back)
+            .bind(uses)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Redemptions of invites promising to follow back which we didn't follow back yet
+    pub async fn get_pending_follow_backs(&self) -> Result<Vec<(String, PublicKey)>> {
+        let rows: Vec<(String, String)> = sqlx::query_as(
+            "
+            SELECT invite_redemptions.invite, invite_redemptions.public_key
+            FROM invite_redemptions
+            JOIN invites ON invites.id = invite_redemptions.invite
+            WHERE invites.follow_back = TRUE AND invite_redemptions.followed_back = FALSE
+            ",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .filter_map(|(id, public_key)| Some((id, public_key.parse().ok()?)))
+            .collect())
+    }
+
+    pub async fn set_followed_back(&self, invite: &str, public_key: &PublicKey) -> Result<()> {
+        sqlx::query(
+            "UPDATE invite_redemptions SET followed_back = TRUE WHERE invite = ? AND public_key = ?",
+        )
+        .bind(invite)
+        .bind(public_key.to_string())
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Everyone who redeemed one of our invites
+    pub async fn get_members(&self) -> Result<Vec<Member>> {
+        let rows: Vec<String> =
+            sqlx::query_scalar("SELECT DISTINCT public_key FROM invite_redemptions")
+                .fetch_all(&self.pool)
+                .await?;
+
+        let mut members = vec![];
+        for public_key in parse_keys(rows) {
+            members.push(self.get_member(&public_key).await);
+        }
+        Ok(members)
+    }
+
+    pub async fn get_member(&self, public_key: &PublicKey) -> Member {
+        Member {
+            profile: self.get_profile(public_key).await,
+            following: self
+                .get_following(&self.public_key)
+                .await
+                .contains(public_key),
+            revoked: self.is_blocked(public_key),
+        }
+    }
+
+    /// Authors we hold logs of and everyone they follow
+    pub async fn get_known_keys(&self) -> Vec<PublicKey> {
+        let unique_keys: Vec<String> = sqlx::query_scalar(
+            "
+            SELECT public_key FROM operations_v1
+            UNION SELECT target FROM follows WHERE state = TRUE
+            ",
+        )
+        .fetch_all(&self.pool)
+        .await
+        .unwrap_or(vec![]);
+        parse_keys(unique_keys)
     }
 }
 
-#[derive(Serialize)]
-pub struct FrontendPost {
-    id: String,
-    public_key: String,
-    timestamp: u64,
-    body: String,
+/// Posts joined with everything needed to render them, filtered further by each caller
+const POST_SELECT: &str = "
+    SELECT posts.id, posts.public_key, posts.timestamp, posts.body,
+        posts.deleted, profiles.name, profiles.avatar, replies.root, replies.parent,
+        EXISTS (
+            SELECT 1 FROM post_revisions
+            WHERE target = posts.id AND public_key = posts.public_key
+        ) AS edited
+    FROM posts
+    LEFT JOIN profiles ON profiles.public_key = posts.public_key
+    LEFT JOIN replies ON replies.id = posts.id
+";
+
+const DEFAULT_PAGE_SIZE: u32 = 50;
+const MAX_PAGE_SIZE: u32 = 500;
+
+/// Split a `timestamp:hash` page cursor
+fn parse_cursor(cursor: &str) -> Option<(i64, String)> {
+    let (timestamp, id) = cursor.split_once(':')?;
+    let id: Hash = id.parse().ok()?;
+    Some((timestamp.parse().ok()?, id.to_string()))
+}
+
+/// Keys blocked by the author bound as the query parameter
+const BLOCKED_KEYS: &str = "SELECT target FROM blocks WHERE public_key = ? AND state = TRUE";
+
+fn parse_keys(keys: Vec<String>) -> Vec<PublicKey> {
+    keys.iter()
+        .filter_map(|public_key| public_key.parse().ok())
+        .collect()
+}
+
+#[derive(sqlx::FromRow)]
+struct ReactionCount {
+    target: String,
+    reaction: String,
+    count: i64,
+    mine: bool,
+}
+
+/// Nest the replies below `post` which are still left in `children`
+fn build_thread(
+    post: FrontendPost,
+    children: &mut HashMap<String, Vec<FrontendPost>>,
+) -> ThreadNode {
+    let replies = children
+        .remove(&post.id)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|reply| build_thread(reply, children))
+        .collect();
+    ThreadNode { post, replies }
+}
+
+/// Materialize an event and apply what it implies for the operation store itself
+async fn apply_event(
+    store: &mut OperationStore,
+    app_data: &AppData,
+    event: &ButtEvent,
+    header: &Header<ButtExtensions>,
+) -> Result<()> {
+    if let ButtEvent::Delete { target } = event {
+        // The post may have reached us without its body, which leaves nothing to materialize for
+        // it. It still needs a placeholder so replies keep their place in the thread.
+        if let Some((target_header, _)) = store.get_operation(*target).await? {
+            if target_header.public_key == header.public_key {
+                app_data.add_tombstone(&target_header).await?;
+            }
+        }
+    }
+
+    app_data.materialize(event, header).await?;
+
+    if let ButtEvent::Delete { target } = event {
+        // Forget the text of the post and of all its edits, but only if they were by the author
+        for hash in app_data.get_post_operation_ids(target).await {
+            let Some((operation_header, _)) = store.get_operation(hash).await? else {
+                continue;
+            };
+            if operation_header.public_key == header.public_key {
+                store.delete_payload(hash).await?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Decode an operation's body and materialize it, quarantining bodies we can't make sense of
+pub async fn process_operation(
+    store: &mut OperationStore,
+    app_data: &AppData,
+    header: &Header<ButtExtensions>,
+    body: &Body,
+) -> Result<()> {
+    match ButtEvent::from_bytes(body.to_bytes()) {
+        Ok(butt_event) => apply_event(store, app_data, &butt_event, header).await,
+        Err(err) => app_data.quarantine(header, body, &err.to_string()).await,
+    }
+}
+
+/// Authors of every log in the operation store
+pub async fn get_log_authors(pool: &Pool) -> Result<Vec<PublicKey>> {
+    let authors: Vec<String> = sqlx::query_scalar("SELECT DISTINCT public_key FROM operations_v1")
+        .fetch_all(pool)
+        .await?;
+    Ok(parse_keys(authors))
+}
+
+/// Throw away all materialized views and rebuild them by replaying every log in the operation
+/// store in seq_num order, returning how many operations were replayed
+pub async fn reindex(store: &mut OperationStore, app_data: &AppData) -> Result<usize> {
+    app_data.truncate().await?;
+
+    let authors = get_log_authors(&app_data.pool).await?;
+    let mut replayed = 0;
+    for (index, author) in authors.iter().enumerate() {
+        let operations = store
+            .get_log(author, &ButtLogId(*author), None)
+            .await?
+            .unwrap_or_default();
+        for (header, body) in &operations {
+            // Bodies of deleted posts are gone, there is nothing to materialize for them
+            if let Some(body) = body {
+                process_operation(store, app_data, header, body).await?;
+            }
+        }
+        replayed += operations.len();
+        println!(
+            "reindexed log {}/{} ({} operations)",
+            index + 1,
+            authors.len(),
+            operations.len()
+        );
+    }
+
+    app_data.set_projection_version(PROJECTION_VERSION).await?;
+    Ok(replayed)
+}
+
+/// Operations from sync used to be filed under our own log id instead of their author's. Move
+/// any of those back into the author's log, returning how many were moved.
+pub async fn repair_log_ids(
+    store: &mut OperationStore,
+    pool: &Pool,
+    public_key: PublicKey,
+) -> Result<usize> {
+    let mut repaired = 0;
+    for author in get_log_authors(pool).await? {
+        if author == public_key {
+            continue;
+        }
+        let Some(operations) = store.get_log(&author, &ButtLogId(public_key), None).await? else {
+            continue;
+        };
+        for (header, body) in operations {
+            store.delete_operation(header.hash()).await?;
+            store
+                .insert_operation(
+                    header.hash(),
+                    &header,
+                    body.as_ref(),
+                    &header.to_bytes(),
+                    &ButtLogId(author),
+                )
+                .await?;
+            repaired += 1;
+        }
+    }
+    Ok(repaired)
+}
+
+/// Bring the operation store and app tables up to date
+async fn run_migrations(pool: &Pool) -> Result<()> {
+    Migrator::new(CombinedMigrationSource::new(vec![
+        operation_store_migrations(),
+        sqlx::migrate!(),
+    ]))
+    .await?
+    .run(pool)
+    .await?;
+    Ok(())
+}
+
+/// Open the databases in the data directory, running migrations and any repairs or reindexing
+/// they need on the way
+pub async fn open_stores(
+    data_path: &Path,
+    public_key: PublicKey,
+    force_reindex: bool,
+) -> Result<(OperationStore, AppData)> {
+    let operation_db_path = format!("{}/operations.db", data_path.display());
+    create_database(&operation_db_path)
+        .await
+        .expect("database file created");
+    let connection_pool = connection_pool(&operation_db_path, 4)
+        .await
+        .unwrap_or_else(|_| panic!("database to exist at {}", &operation_db_path));
+
+    run_migrations(&connection_pool).await?;
+
+    let mut store = OperationStore::new(connection_pool.clone());
+
+    let repaired = repair_log_ids(&mut store, &connection_pool, public_key).await?;
+    if repaired > 0 {
+        println!("moved {} operations back into their author's log", repaired);
+    }
+
+    let app_data = AppData::new(connection_pool, public_key).await;
+
+    if force_reindex || app_data.projection_version().await.as_deref() != Some(PROJECTION_VERSION) {
+        println!("rebuilding app database from the operation store");
+        let replayed = reindex(&mut store, &app_data).await?;
+        println!("reindexed {} operations", replayed);
+    }
+
+    Ok((store, app_data))
 }
 
 pub struct Backend {
-    #[allow(dead_code)]
     node: ButtNode,
     pub private_key: PrivateKey,
     store: OperationStore,
@@ -145,34 +1216,28 @@ pub struct Backend {
 }
 
 impl Backend {
-    pub async fn new(private_key: PrivateKey, data_path: String) -> Result<Self> {
-        let operation_db_path = format!("{}/operations.db", data_path);
-        create_database(&operation_db_path)
-            .await
-            .expect("database file created");
-        let connection_pool = connection_pool(&operation_db_path, 4)
-            .await
-            .unwrap_or_else(|_| panic!("database to exist at {}", &operation_db_path));
-
-        Migrator::new(CombinedMigrationSource::new(vec![
-            operation_store_migrations(),
-            sqlx::migrate!(),
-        ]))
-        .await?
-        .run(&connection_pool)
-        .await?;
-
-        let store = OperationStore::new(connection_pool.clone());
+    pub async fn new(
+        private_key: PrivateKey,
+        config: &Config,
+        force_reindex: bool,
+    ) -> Result<Self> {
+        let public_key = private_key.public_key();
+        let (store, app_data) = open_stores(&config.data_dir, public_key, force_reindex).await?;
 
         let (tx, mut rx_from_sync) = mpsc::channel::<(Header<ButtExtensions>, Body)>(10000);
 
-        let app_data = AppData::new(connection_pool.clone()).await;
-        let topic_map = topic::ButtLogMap::new(store.clone(), app_data.clone());
-
-        let public_key = private_key.public_key();
+        let topic_map = topic::ButtLogMap::new(store.clone(), app_data.clone(), public_key);
 
         let backend = Backend {
-            node: ButtNode::new(store.clone(), private_key.clone(), tx, topic_map.clone()).await,
+            node: ButtNode::new(
+                store.clone(),
+                private_key.clone(),
+                tx,
+                topic_map.clone(),
+                app_data.clone(),
+                config,
+            )
+            .await,
             private_key,
             store: store.clone(),
             app_data: app_data.clone(),
@@ -183,26 +1248,26 @@ impl Backend {
             let mut store = store;
 
             while let Some((header, body)) = rx_from_sync.recv().await {
+                // The node's ingest already stored the operation in its author's log
                 println!("Event arrived from sync into materializer ✏️");
-                let _ = store
-                    .insert_operation(
-                        header.hash(),
-                        &header,
-                        Some(&body),
-                        &header.to_bytes(),
-                        &ButtLogId(public_key),
-                    )
-                    .await;
-
-                let butt_event = ButtEvent::from_bytes(body.to_bytes());
-                app_data.materialize(&butt_event, &header).await;
+                if let Err(err) = process_operation(&mut store, &app_data, &header, &body).await {
+                    println!("could not materialize operation {}: {}", header.hash(), err);
+                }
             }
         });
 
         Ok(backend)
     }
 
-    pub async fn create_operation(&mut self, body: &[u8]) -> (Header<ButtExtensions>, Body) {
+    pub async fn status(&self) -> NodeStatus {
+        self.node.status().await
+    }
+
+    pub async fn create_operation(
+        &mut self,
+        body: &[u8],
+        prune: bool,
+    ) -> Result<(Header<ButtExtensions>, Body)> {
         let body = Body::new(body);
         let public_key = self.private_key.public_key();
 
@@ -213,11 +1278,7 @@ impl Backend {
 
         let log_id = ButtLogId(public_key);
 
-        let latest_operation = self
-            .store
-            .latest_operation(&public_key, &log_id)
-            .await
-            .unwrap();
+        let latest_operation = self.store.latest_operation(&public_key, &log_id).await?;
 
         let (seq_num, backlink) = match latest_operation {
             Some((header, _)) => (header.seq_num + 1, Some(header.hash())),
@@ -234,11 +1295,13 @@ impl Backend {
             seq_num,
             backlink,
             previous: vec![],
-            extensions: Some(ButtExtensions::default()),
+            extensions: Some(ButtExtensions {
+                prune_flag: PruneFlag::new(prune),
+            }),
         };
         header.sign(&self.private_key);
 
-        (header, body)
+        Ok((header, body))
     }
 
     pub async fn insert_operation(
@@ -246,9 +1309,8 @@ impl Backend {
         body: &Body,
         header: &Header<ButtExtensions>,
         event: &ButtEvent,
-    ) {
-        let _ = self
-            .store
+    ) -> Result<()> {
+        self.store
             .insert_operation(
                 header.hash(),
                 header,
@@ -256,29 +1318,503 @@ impl Backend {
                 &header.to_bytes(),
                 &ButtLogId(self.private_key.public_key()),
             )
-            .await;
+            .await?;
 
-        self.app_data.materialize(event, header).await;
+        apply_event(&mut self.store, &self.app_data, event, header).await
     }
 
-    #[allow(dead_code)]
-    pub async fn follow(&mut self, friend_key: PublicKey) -> (ButtEvent, Header<ButtExtensions>) {
+    pub async fn follow(
+        &mut self,
+        friend_key: PublicKey,
+    ) -> Result<(ButtEvent, Header<ButtExtensions>)> {
         println!("Following my new friend: {}", friend_key);
         let follow = ButtEvent::Follow(friend_key);
-        let (header, body) = self.create_operation(&follow.to_bytes()).await;
+        let (header, body) = self.create_operation(&follow.to_bytes(), false).await?;
 
-        self.insert_operation(&body, &header, &follow).await;
+        self.insert_operation(&body, &header, &follow).await?;
+        self.node.send_gossip(header.clone(), body.clone()).await;
+        Ok((follow, header))
+    }
 
-        (follow, header)
+    pub async fn unfollow(
+        &mut self,
+        friend_key: PublicKey,
+    ) -> Result<(ButtEvent, Header<ButtExtensions>)> {
+        println!("Unfollowing: {}", friend_key);
+        let unfollow = ButtEvent::Unfollow(friend_key);
+        let (header, body) = self.create_operation(&unfollow.to_bytes(), false).await?;
+
+        self.insert_operation(&body, &header, &unfollow).await?;
+        self.node.send_gossip(header.clone(), body.clone()).await;
+        Ok((unfollow, header))
+    }
+
+    pub async fn update_about(
+        &mut self,
+        about: ButtAbout,
+    ) -> Result<(ButtEvent, Header<ButtExtensions>)> {
+        println!("Updating my about");
+        let about = ButtEvent::About(about);
+        let (header, body) = self.create_operation(&about.to_bytes(), false).await?;
+
+        self.insert_operation(&body, &header, &about).await?;
+        self.node.send_gossip(header.clone(), body.clone()).await;
+        Ok((about, header))
     }
 
-    pub async fn create_post(&mut self, post_body: String) -> (ButtEvent, Header<ButtExtensions>) {
+    pub async fn block(&mut self, key: PublicKey) -> Result<(ButtEvent, Header<ButtExtensions>)> {
+        println!("Blocking: {}", key);
+        let block = ButtEvent::Block(key);
+        let (header, body) = self.create_operation(&block.to_bytes(), false).await?;
+
+        self.insert_operation(&body, &header, &block).await?;
+
+        Ok((block, header))
+    }
+
+    pub async fn unblock(&mut self, key: PublicKey) -> Result<(ButtEvent, Header<ButtExtensions>)> {
+        println!("Unblocking: {}", key);
+        let unblock = ButtEvent::Unblock(key);
+        let (header, body) = self.create_operation(&unblock.to_bytes(), false).await?;
+
+        self.insert_operation(&body, &header, &unblock).await?;
+
+        Ok((unblock, header))
+    }
+
+    pub async fn react(
+        &mut self,
+        target: Hash,
+        reaction: String,
+    ) -> Result<(ButtEvent, Header<ButtExtensions>)> {
+        println!("Reacting {} to {}", reaction, target);
+        let react = ButtEvent::React { target, reaction };
+        let (header, body) = self.create_operation(&react.to_bytes(), false).await?;
+
+        self.insert_operation(&body, &header, &react).await?;
+        self.node.send_gossip(header.clone(), body.clone()).await;
+        Ok((react, header))
+    }
+
+    pub async fn unreact(
+        &mut self,
+        target: Hash,
+        reaction: String,
+    ) -> Result<(ButtEvent, Header<ButtExtensions>)> {
+        println!("Retracting {} from {}", reaction, target);
+        let unreact = ButtEvent::Unreact { target, reaction };
+        let (header, body) = self.create_operation(&unreact.to_bytes(), false).await?;
+
+        self.insert_operation(&body, &header, &unreact).await?;
+        self.node.send_gossip(header.clone(), body.clone()).await;
+        Ok((unreact, header))
+    }
+
+    pub async fn edit_post(
+        &mut self,
+        target: Hash,
+        new_body: String,
+    ) -> Result<(ButtEvent, Header<ButtExtensions>)> {
+        println!("Editing post {}", target);
+        self.check_own_post(&target).await?;
+        let edit = ButtEvent::Edit { target, new_body };
+        let (header, body) = self.create_operation(&edit.to_bytes(), false).await?;
+
+        self.insert_operation(&body, &header, &edit).await?;
+        self.node.send_gossip(header.clone(), body.clone()).await;
+        Ok((edit, header))
+    }
+
+    pub async fn delete_post(
+        &mut self,
+        target: Hash,
+    ) -> Result<(ButtEvent, Header<ButtExtensions>)> {
+        println!("Deleting post {}", target);
+        self.check_own_post(&target).await?;
+        let delete = ButtEvent::Delete { target };
+        let (header, body) = self.create_operation(&delete.to_bytes(), false).await?;
+
+        self.insert_operation(&body, &header, &delete).await?;
+        self.node.send_gossip(header.clone(), body.clone()).await;
+        Ok((delete, header))
+    }
+
+    /// Signed invite code carrying everything needed to reach and follow us, which can be
+    /// redeemed `uses` times or without limit when `None`
+    pub async fn create_invite(&mut self, follow_back: bool, uses: Option<u32>) -> Result<String> {
+        let status = self.node.status().await;
+        let invite = Invite {
+            id: Hash::new(rand::random::<[u8; 32]>()),
+            public_key: status.public_key,
+            addresses: status.direct_addresses,
+            network_id: status.network_id,
+            follow_back,
+        };
+        self.app_data.add_invite(&invite, uses).await?;
+        println!("Created invite {}", invite.id);
+        invite.encode(&self.private_key)
+    }
+
+    /// Follow the inviter, dial them directly and tell them we redeemed their invite
+    pub async fn redeem_invite(
+        &mut self,
+        code: &str,
+    ) -> Result<(ButtEvent, Header<ButtExtensions>)> {
+        let invite =
+            Invite::decode(code).map_err(|err| InvalidInput(format!("invalid invite: {}", err)))?;
+        let status = self.node.status().await;
+        if invite.network_id != status.network_id {
+            return Err(InvalidInput("invite is for another network".to_string()).into());
+        }
+        if invite.public_key == status.public_key {
+            return Err(InvalidInput("invite is our own".to_string()).into());
+        }
+        println!("Redeeming invite of {}", invite.public_key);
+
+        let peer = PeerAddress {
+            public_key: invite.public_key,
+            addresses: invite.addresses,
+        };
+        if let Err(err) = self.node.add_peer(&peer).await {
+            println!("could not add {} as a peer: {}", peer.public_key, err);
+        }
+
+        self.follow(invite.public_key).await?;
+
+        let redeem = ButtEvent::RedeemInvite {
+            inviter: invite.public_key,
+            proof: redemption_proof(&invite.id, &status.public_key),
+        };
+        let (header, body) = self.create_operation(&redeem.to_bytes(), false).await?;
+
+        self.insert_operation(&body, &header, &redeem).await?;
+        self.node.send_gossip(header.clone(), body.clone()).await;
+        Ok((redeem, header))
+    }
+
+    /// Keep the promise of invites asking us to follow whoever redeemed them
+    pub async fn follow_back(&mut self) -> Result<()> {
+        let public_key = self.private_key.public_key();
+        for (invite, redeemer) in self.app_data.get_pending_follow_backs().await? {
+            let following = self.app_data.get_following(&public_key).await;
+            if !following.contains(&redeemer) && !self.app_data.is_blocked(&redeemer) {
+                self.follow(redeemer).await?;
+            }
+            self.app_data.set_followed_back(&invite, &redeemer).await?;
+        }
+        Ok(())
+    }
+
+    /// Stop replicating a member and drop everything of theirs, which also keeps us from
+    /// following them back again
+    pub async fn revoke_member(
+        &mut self,
+        public_key: PublicKey,
+    ) -> Result<(ButtEvent, Header<ButtExtensions>)> {
+        println!("Revoking member {}", public_key);
+        if self
+            .app_data
+            .get_following(&self.private_key.public_key())
+            .await
+            .contains(&public_key)
+        {
+            self.unfollow(public_key).await?;
+        }
+        self.block(public_key).await
+    }
+
+    /// Edits and deletes of posts by anyone else would be ignored by every peer
+    async fn check_own_post(&self, target: &Hash) -> Result<()> {
+        match self.app_data.get_post(target).await {
+            Some(post) if post.deleted => {
+                Err(InvalidInput(format!("{} is already deleted", target)).into())
+            }
+            Some(post) if post.public_key == self.private_key.public_key().to_string() => Ok(()),
+            Some(_) => Err(InvalidInput(format!("{} is not one of your posts", target)).into()),
+            None => Err(InvalidInput(format!("unknown post {}", target)).into()),
+        }
+    }
+
+    /// Drop our whole log history up to a new operation carrying the prune flag, peers remove
+    /// everything before it on ingest. The pruning operation republishes our current profile and
+    /// is followed by our current follows and blocks, so a reindex or a peer meeting us for the
+    /// first time still knows whose logs to replicate. Posts are gone for anyone who had not
+    /// replicated them yet.
+    pub async fn prune_history(&mut self) -> Result<(ButtEvent, Header<ButtExtensions>)> {
+        println!("Pruning my log history");
+        let public_key = self.private_key.public_key();
+        let following = self.app_data.get_following(&public_key).await;
+        let blocked = self.app_data.get_blocked();
+
+        let about = ButtEvent::About(self.app_data.get_about(&public_key).await);
+        let (header, body) = self.create_operation(&about.to_bytes(), true).await?;
+
+        self.insert_operation(&body, &header, &about).await?;
+        self.store
+            .delete_operations(&public_key, &ButtLogId(public_key), header.seq_num)
+            .await?;
+        self.node.send_gossip(header.clone(), body.clone()).await;
+
+        for key in following {
+            self.follow(key).await?;
+        }
+        for key in blocked {
+            self.block(key).await?;
+        }
+        Ok((about, header))
+    }
+
+    pub async fn create_post(
+        &mut self,
+        mut post: ButtPostContent,
+    ) -> Result<(ButtEvent, Header<ButtExtensions>)> {
         println!("Creating a post!");
-        let post = ButtEvent::Post(post_body);
-        let (header, body) = self.create_operation(&post.to_bytes()).await;
+        if let (None, Some(parent)) = (post.root, post.parent) {
+            post.root = Some(self.app_data.get_thread_root(&parent).await);
+        }
+        let post = ButtEvent::Post(post);
+        let (header, body) = self.create_operation(&post.to_bytes(), false).await?;
 
-        self.insert_operation(&body, &header, &post).await;
+        self.insert_operation(&body, &header, &post).await?;
         self.node.send_gossip(header.clone(), body.clone()).await;
-        (post, header)
+        Ok((post, header))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    use super::*;
+
+    /// App data on a fresh in-memory database, a single connection keeps it alive
+    async fn app_data(public_key: PublicKey) -> AppData {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        run_migrations(&pool).await.unwrap();
+        AppData::new(pool, public_key).await
+    }
+
+    fn header(private_key: &PrivateKey, seq_num: u64) -> Header<ButtExtensions> {
+        let mut header = Header {
+            version: 1,
+            public_key: private_key.public_key(),
+            signature: None,
+            payload_size: 0,
+            payload_hash: None,
+            timestamp: 1700000000 + seq_num,
+            seq_num,
+            backlink: None,
+            previous: vec![],
+            extensions: Some(ButtExtensions::default()),
+        };
+        header.sign(private_key);
+        header
+    }
+
+    #[tokio::test]
+    async fn latest_follow_state_wins() {
+        let me = PrivateKey::new();
+        let friend = PrivateKey::new().public_key();
+        let app_data = app_data(me.public_key()).await;
+
+        app_data
+            .materialize(&ButtEvent::Follow(friend), &header(&me, 2))
+            .await
+            .unwrap();
+        app_data
+            .materialize(&ButtEvent::Unfollow(friend), &header(&me, 1))
+            .await
+            .unwrap();
+        assert_eq!(app_data.get_following(&me.public_key()).await, vec![friend]);
+
+        app_data
+            .materialize(&ButtEvent::Unfollow(friend), &header(&me, 3))
+            .await
+            .unwrap();
+        assert!(app_data.get_following(&me.public_key()).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn about_fields_win_on_their_own() {
+        let me = PrivateKey::new();
+        let app_data = app_data(me.public_key()).await;
+        let about = |name: Option<&str>, avatar: Option<&str>| {
+            ButtEvent::About(ButtAbout {
+                name: name.map(String::from),
+                description: None,
+                avatar: avatar.map(String::from),
+            })
+        };
+
+        // Applied out of order, like a sync delivering an older operation late
+        let events = [
+            (2, about(None, Some("new avatar"))),
+            (3, about(Some("new name"), None)),
+            (1, about(Some("old name"), Some("old avatar"))),
+        ];
+        for (seq_num, event) in &events {
+            app_data
+                .materialize(event, &header(&me, *seq_num))
+                .await
+                .unwrap();
+        }
+
+        let profile = app_data.get_profile(&me.public_key()).await;
+        assert_eq!(profile.name.as_deref(), Some("new name"));
+        assert_eq!(profile.avatar.as_deref(), Some("new avatar"));
+        assert_eq!(profile.description, None);
+    }
+
+    #[tokio::test]
+    async fn replies_link_up_when_arriving_out_of_order() {
+        let me = PrivateKey::new();
+        let app_data = app_data(me.public_key()).await;
+        let post = |parent: Option<Hash>| {
+            ButtEvent::Post(ButtPostContent {
+                body: "hello".to_string(),
+                root: None,
+                parent,
+            })
+        };
+
+        let root = header(&me, 0);
+        let parent = header(&me, 1);
+        let child = header(&me, 2);
+        for (event, header) in [
+            (post(Some(parent.hash())), &child),
+            (post(Some(root.hash())), &parent),
+            (post(None), &root),
+        ] {
+            app_data.materialize(&event, header).await.unwrap();
+        }
+
+        let thread = app_data.get_thread(&root.hash()).await.unwrap();
+        assert_eq!(thread.post.id, root.hash().to_string());
+        assert_eq!(thread.replies.len(), 1);
+        assert_eq!(thread.replies[0].post.id, parent.hash().to_string());
+        assert_eq!(thread.replies[0].replies.len(), 1);
+        assert_eq!(
+            thread.replies[0].replies[0].post.id,
+            child.hash().to_string()
+        );
+        assert_eq!(app_data.get_thread_root(&child.hash()).await, root.hash());
+    }
+
+    #[tokio::test]
+    async fn deleting_a_post_without_body_leaves_a_placeholder() {
+        let me = PrivateKey::new();
+        let app_data = app_data(me.public_key()).await;
+        let mut store = OperationStore::new(app_data.pool.clone());
+
+        // Like after a reindex or a sync, the post's body was already deleted
+        let post = header(&me, 0);
+        store
+            .insert_operation(
+                post.hash(),
+                &post,
+                None,
+                &post.to_bytes(),
+                &ButtLogId(me.public_key()),
+            )
+            .await
+            .unwrap();
+
+        let delete = ButtEvent::Delete {
+            target: post.hash(),
+        };
+        apply_event(&mut store, &app_data, &delete, &header(&me, 1))
+            .await
+            .unwrap();
+
+        let placeholder = app_data.get_post(&post.hash()).await.unwrap();
+        assert!(placeholder.deleted);
+        assert_eq!(placeholder.body, "");
+        assert_eq!(placeholder.timestamp, post.timestamp as i64);
+
+        // Deleting someone else's post leaves nothing behind
+        let other = header(&PrivateKey::new(), 0);
+        store
+            .insert_operation(
+                other.hash(),
+                &other,
+                None,
+                &other.to_bytes(),
+                &ButtLogId(other.public_key),
+            )
+            .await
+            .unwrap();
+        let delete = ButtEvent::Delete {
+            target: other.hash(),
+        };
+        apply_event(&mut store, &app_data, &delete, &header(&me, 2))
+            .await
+            .unwrap();
+        assert!(app_data.get_post(&other.hash()).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn react_and_unreact_toggle() {
+        let me = PrivateKey::new();
+        let author = PrivateKey::new();
+        let app_data = app_data(me.public_key()).await;
+
+        let post = header(&author, 0);
+        let content = ButtPostContent {
+            body: "hello".to_string(),
+            root: None,
+            parent: None,
+        };
+        app_data
+            .materialize(&ButtEvent::Post(content), &post)
+            .await
+            .unwrap();
+
+        let react = ButtEvent::React {
+            target: post.hash(),
+            reaction: "❤️".to_string(),
+        };
+        app_data.materialize(&react, &header(&me, 1)).await.unwrap();
+        app_data
+            .materialize(&react, &header(&author, 1))
+            .await
+            .unwrap();
+        let liked = app_data.get_post(&post.hash()).await.unwrap();
+        assert_eq!(liked.reactions.get("❤️"), Some(&2));
+        assert!(liked.reacted_by_me);
+
+        let unreact = ButtEvent::Unreact {
+            target: post.hash(),
+            reaction: "❤️".to_string(),
+        };
+        app_data
+            .materialize(&unreact, &header(&me, 2))
+            .await
+            .unwrap();
+        let unliked = app_data.get_post(&post.hash()).await.unwrap();
+        assert_eq!(unliked.reactions.get("❤️"), Some(&1));
+        assert!(!unliked.reacted_by_me);
+
+        // A late copy of the reaction doesn't undo the newer unreact
+        app_data.materialize(&react, &header(&me, 1)).await.unwrap();
+        let unliked = app_data.get_post(&post.hash()).await.unwrap();
+        assert!(!unliked.reacted_by_me);
+    }
+
+    #[test]
+    fn parses_cursor() {
+        let id = Hash::new(b"post");
+        assert_eq!(
+            parse_cursor(&format!("1700000000:{}", id)),
+            Some((1700000000, id.to_string()))
+        );
+
+        assert_eq!(parse_cursor("1700000000"), None);
+        assert_eq!(parse_cursor(&format!("soon:{}", id)), None);
+        assert_eq!(parse_cursor("1700000000:nope"), None);
+        assert_eq!(parse_cursor(""), None);
     }
 }
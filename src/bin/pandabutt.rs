@@ -0,0 +1,442 @@
+// command line client talking to the HTTP API of a running node
+
+use anyhow::{bail, Result};
+use clap::{Parser, Subcommand};
+use p2panda_core::{Hash, PublicKey};
+use panda_butt::api::{
+    AboutInput, BlockState, CreatedOperation, ErrorResponse, FollowInput, FollowState,
+    FrontendPost, Identity, InviteCode, InviteInput, LiveEvent, Member, MemberInput, NodeStatus,
+    PostBodyInput, PostPage, Profile, TOKEN_FILE,
+};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::HashSet;
+use std::fs;
+use std::path::PathBuf;
+
+#[derive(Parser, Debug)]
+#[command(
+    name = "pandabutt",
+    version,
+    about = "Command line client for a running panda-butt node"
+)]
+struct Cli {
+    /// Base URL of the node's HTTP API
+    #[arg(
+        long,
+        global = true,
+        env = "PANDABUTT_URL",
+        default_value = "http://localhost:8000"
+    )]
+    url: String,
+
+    /// API token of the node, read from the node's data directory when not given
+    #[arg(long, global = true, env = "PANDABUTT_TOKEN")]
+    token: Option<String>,
+
+    /// Data directory of the node, only used to find its API token
+    #[arg(
+        long,
+        global = true,
+        env = "PANDABUTT_DATA_DIR",
+        default_value = "./data"
+    )]
+    data_dir: PathBuf,
+
+    /// Print JSON instead of text, one document per line
+    #[arg(long, global = true)]
+    json: bool,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Print the node's public key
+    Id,
+    /// Print the node's network id, relay and addresses
+    Status,
+    /// Publish a post, optionally as a reply within a thread
+    Post {
+        body: String,
+        /// First post of the thread
+        #[arg(long)]
+        root: Option<Hash>,
+        /// Post being replied to
+        #[arg(long)]
+        parent: Option<Hash>,
+    },
+    /// Follow someone
+    Follow { public_key: PublicKey },
+    /// Stop following someone
+    Unfollow { public_key: PublicKey },
+    /// Block someone, dropping their logs and posts
+    Block { public_key: PublicKey },
+    /// Stop blocking someone
+    Unblock { public_key: PublicKey },
+    /// Print the newest posts, oldest first
+    Feed {
+        /// How many posts to print
+        #[arg(long, default_value_t = 50)]
+        limit: u32,
+        /// Only print posts of this author
+        #[arg(long)]
+        author: Option<PublicKey>,
+    },
+    /// Create an invite code for someone to reach and follow the node
+    Invite {
+        /// Follow whoever redeems the invite first
+        #[arg(long)]
+        follow_back: bool,
+    },
+    /// Redeem an invite code, following the inviter
+    Redeem { code: String },
+    /// List everyone who redeemed an invite of the pub
+    Members,
+    /// Unfollow and block a member of the pub
+    Revoke { public_key: PublicKey },
+    /// Publish the node's profile, fields left out keep their previous value
+    About {
+        #[arg(long)]
+        name: Option<String>,
+        #[arg(long)]
+        description: Option<String>,
+        /// base64 encoded image
+        #[arg(long)]
+        avatar: Option<String>,
+    },
+    /// Print someone's profile
+    Profile { public_key: PublicKey },
+    /// Keep printing new posts as they arrive
+    Tail,
+}
+
+struct Client {
+    http: reqwest::Client,
+    url: String,
+    token: Option<String>,
+}
+
+impl Client {
+    async fn get<T: DeserializeOwned>(&self, path: &str) -> Result<T> {
+        let mut request = self.http.get(format!("{}{}", self.url, path));
+        if let Some(token) = &self.token {
+            request = request.bearer_auth(token);
+        }
+        let response = request.send().await?;
+        if !response.status().is_success() {
+            bail!("{} failed: {}", path, error_message(response).await);
+        }
+        Ok(response.json().await?)
+    }
+
+    async fn post<T: Serialize, R: DeserializeOwned>(&self, path: &str, input: &T) -> Result<R> {
+        let mut request = self.http.post(format!("{}{}", self.url, path)).json(input);
+        if let Some(token) = &self.token {
+            request = request.bearer_auth(token);
+        }
+        let response = request.send().await?;
+        if !response.status().is_success() {
+            bail!("{} failed: {}", path, error_message(response).await);
+        }
+        Ok(response.json().await?)
+    }
+
+    /// Stream of the node's `/events`, read it with `next_event`
+    async fn events(&self) -> Result<reqwest::Response> {
+        let response = self.http.get(format!("{}/events", self.url)).send().await?;
+        if !response.status().is_success() {
+            bail!("/events failed: {}", error_message(response).await);
+        }
+        Ok(response)
+    }
+
+    /// The newest posts matching the query string, oldest first
+    async fn posts(&self, query: &str) -> Result<Vec<FrontendPost>> {
+        let page: PostPage = self.get(&format!("/posts?{}", query)).await?;
+        let mut posts = page.posts;
+        posts.reverse();
+        Ok(posts)
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let cli = Cli::parse();
+    let token = cli.token.or_else(|| {
+        fs::read_to_string(cli.data_dir.join(TOKEN_FILE))
+            .ok()
+            .map(|token| token.trim().to_string())
+    });
+    let client = Client {
+        http: reqwest::Client::new(),
+        url: cli.url.trim_end_matches('/').to_string(),
+        token,
+    };
+
+    match cli.command {
+        Command::Id => {
+            let identity: Identity = client.get("/id").await?;
+            if cli.json {
+                print_json(&identity);
+            } else {
+                println!("{}", identity.public_key);
+            }
+        }
+        Command::Status => {
+            let status: NodeStatus = client.get("/status").await?;
+            if cli.json {
+                print_json(&status);
+            } else {
+                println!("public key: {}", status.public_key);
+                println!("network id: {}", status.network_id);
+                println!("relay: {}", status.relay.as_deref().unwrap_or("-"));
+                println!(
+                    "configured relay: {}",
+                    status.configured_relay.as_deref().unwrap_or("-")
+                );
+                for address in &status.direct_addresses {
+                    println!("address: {}", address);
+                }
+            }
+        }
+        Command::Post { body, root, parent } => {
+            let created: CreatedOperation<FrontendPost> = client
+                .post("/post", &PostBodyInput { body, root, parent })
+                .await?;
+            if cli.json {
+                print_json(&created);
+            } else {
+                println!("{}", created.hash);
+            }
+        }
+        Command::Follow { public_key } => {
+            let created = client.post("/follow", &FollowInput { public_key }).await?;
+            print_follow(&created, cli.json);
+        }
+        Command::Unfollow { public_key } => {
+            let created = client
+                .post("/unfollow", &FollowInput { public_key })
+                .await?;
+            print_follow(&created, cli.json);
+        }
+        Command::Block { public_key } => {
+            let created = client.post("/block", &FollowInput { public_key }).await?;
+            print_block(&created, cli.json);
+        }
+        Command::Unblock { public_key } => {
+            let created = client.post("/unblock", &FollowInput { public_key }).await?;
+            print_block(&created, cli.json);
+        }
+        Command::Invite { follow_back } => {
+            let invite: InviteCode = client.post("/invite", &InviteInput { follow_back }).await?;
+            if cli.json {
+                print_json(&invite);
+            } else {
+                println!("{}", invite.code);
+            }
+        }
+        Command::Redeem { code } => {
+            let created = client.post("/redeem", &InviteCode { code }).await?;
+            print_follow(&created, cli.json);
+        }
+        Command::Members => {
+            let members: Vec<Member> = client.get("/admin/members").await?;
+            for member in &members {
+                print_member(member, cli.json);
+            }
+        }
+        Command::Revoke { public_key } => {
+            let created: CreatedOperation<Member> = client
+                .post("/admin/revoke", &MemberInput { public_key })
+                .await?;
+            if cli.json {
+                print_json(&created);
+            } else {
+                print_member(&created.view, false);
+            }
+        }
+        Command::Feed { limit, author } => {
+            let mut query = format!("limit={}", limit);
+            if let Some(author) = author {
+                query.push_str(&format!("&author={}", author));
+            }
+            for post in client.posts(&query).await? {
+                print_post(&post, cli.json);
+            }
+        }
+        Command::About {
+            name,
+            description,
+            avatar,
+        } => {
+            let input = AboutInput {
+                name,
+                description,
+                avatar,
+            };
+            let created: CreatedOperation<Profile> = client.post("/about", &input).await?;
+            if cli.json {
+                print_json(&created);
+            } else {
+                print_profile(&created.view);
+            }
+        }
+        Command::Profile { public_key } => {
+            let profile: Profile = client.get(&format!("/profile/{}", public_key)).await?;
+            if cli.json {
+                print_json(&profile);
+            } else {
+                print_profile(&profile);
+            }
+        }
+        Command::Tail => {
+            // Edits and reactions send a post again, only its first appearance is printed
+            let mut seen: HashSet<String> = client
+                .posts("")
+                .await?
+                .into_iter()
+                .map(|post| post.id)
+                .collect();
+            let mut events = client.events().await?;
+            let mut buffer = vec![];
+            while let Some(chunk) = events.chunk().await? {
+                buffer.extend_from_slice(&chunk);
+                while let Some(event) = next_event(&mut buffer) {
+                    if let LiveEvent::Post { post } = event {
+                        if !post.deleted && seen.insert(post.id.clone()) {
+                            print_post(&post, cli.json);
+                        }
+                    }
+                }
+            }
+            bail!("the node closed the event stream");
+        }
+    }
+
+    Ok(())
+}
+
+/// Take the next complete server-sent event off the buffer, skipping comments and anything
+/// which is not a live event
+fn next_event(buffer: &mut Vec<u8>) -> Option<LiveEvent> {
+    loop {
+        let end = buffer.windows(2).position(|window| window == b"\n\n")?;
+        let message: Vec<u8> = buffer.drain(..end + 2).collect();
+        let message = String::from_utf8_lossy(&message);
+        let data: Vec<&str> = message
+            .lines()
+            .filter_map(|line| line.strip_prefix("data:"))
+            .map(|data| data.strip_prefix(' ').unwrap_or(data))
+            .collect();
+        if let Ok(event) = serde_json::from_str(&data.join("\n")) {
+            return Some(event);
+        }
+    }
+}
+
+/// The error a node sent along with a failed request, falling back to the status code
+async fn error_message(response: reqwest::Response) -> String {
+    let status = response.status();
+    match response.json::<ErrorResponse>().await {
+        Ok(body) => format!("{} ({})", body.error, status),
+        Err(_) => status.to_string(),
+    }
+}
+
+fn print_json<T: Serialize>(value: &T) {
+    println!(
+        "{}",
+        serde_json::to_string(value).expect("value converted to json")
+    );
+}
+
+fn print_follow(created: &CreatedOperation<FollowState>, json: bool) {
+    if json {
+        print_json(created);
+    } else if created.view.following {
+        println!("following {}", created.view.target);
+    } else {
+        println!("not following {}", created.view.target);
+    }
+}
+
+fn print_block(created: &CreatedOperation<BlockState>, json: bool) {
+    if json {
+        print_json(created);
+    } else if created.view.blocked {
+        println!("blocking {}", created.view.target);
+    } else {
+        println!("not blocking {}", created.view.target);
+    }
+}
+
+fn print_profile(profile: &Profile) {
+    println!("{}", profile.public_key);
+    println!("name: {}", profile.name.as_deref().unwrap_or("-"));
+    println!(
+        "description: {}",
+        profile.description.as_deref().unwrap_or("-")
+    );
+}
+
+fn print_member(member: &Member, json: bool) {
+    if json {
+        print_json(member);
+        return;
+    }
+    let state = if member.revoked {
+        "revoked"
+    } else if member.following {
+        "following"
+    } else {
+        "not following"
+    };
+    println!(
+        "{} {} ({})",
+        member.profile.public_key,
+        member.profile.name.as_deref().unwrap_or("-"),
+        state
+    );
+}
+
+fn print_post(post: &FrontendPost, json: bool) {
+    if json {
+        print_json(post);
+        return;
+    }
+    let author = post
+        .name
+        .clone()
+        .unwrap_or_else(|| post.public_key.chars().take(9).collect());
+    println!(
+        "{} {} {}: {}",
+        post.timestamp,
+        &post.id[..9],
+        author,
+        post.body
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use p2panda_core::PrivateKey;
+
+    use super::*;
+
+    #[test]
+    fn reads_events_split_across_chunks() {
+        let key = PrivateKey::new().public_key();
+        let mut buffer = b":\n\ndata:{\"type\":\"invite_redeemed\",".to_vec();
+        assert!(next_event(&mut buffer).is_none());
+
+        buffer.extend_from_slice(format!("\"public_key\":\"{}\"}}\n\ndata:{{", key).as_bytes());
+        assert!(matches!(
+            next_event(&mut buffer),
+            Some(LiveEvent::InviteRedeemed { public_key }) if public_key == key
+        ));
+        assert!(next_event(&mut buffer).is_none());
+        assert_eq!(buffer, b"data:{");
+    }
+}
@@ -0,0 +1,299 @@
+// command line, environment and config file handling
+
+use anyhow::{Context, Result};
+use clap::{Args, Parser, Subcommand};
+use p2panda_core::Hash;
+use serde::Deserialize;
+use std::fs;
+use std::net::{IpAddr, Ipv4Addr};
+use std::path::PathBuf;
+
+use crate::peers::PeerAddress;
+
+const DEFAULT_DATA_DIR: &str = "./data";
+const DEFAULT_PORT: u16 = 8000;
+const DEFAULT_NETWORK_ID: &str = "butt-net";
+const DEFAULT_HOPS: u8 = 1;
+/// Pubs replicate their members and whoever the members follow
+const DEFAULT_PUB_HOPS: u8 = 2;
+
+#[derive(Parser, Debug)]
+#[command(
+    name = "panda-butt",
+    version,
+    about = "A p2panda take on Secure Scuttlebutt"
+)]
+pub struct Cli {
+    /// Directory holding the private key and databases
+    #[arg(long, global = true, env = "PANDABUTT_DATA_DIR")]
+    pub data_dir: Option<PathBuf>,
+
+    /// TOML config file, defaults to `config.toml` inside of the data directory
+    #[arg(long, global = true, env = "PANDABUTT_CONFIG")]
+    pub config: Option<PathBuf>,
+
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Start the node together with its web UI
+    Run(RunArgs),
+    /// Create the data directory with a new private key and API token
+    Init,
+    /// Print our public key
+    Id,
+    /// Write every log in the operation store to a file
+    Export { path: PathBuf },
+    /// Add the operations of an exported file to the operation store
+    Import { path: PathBuf },
+    /// Rebuild the app database from the operation store
+    Reindex,
+    /// Check signatures, payloads and backlinks of every stored operation
+    Verify,
+}
+
+#[derive(Args, Debug)]
+pub struct RunArgs {
+    /// Port of the HTTP API and web UI
+    #[arg(long, env = "PANDABUTT_PORT")]
+    pub port: Option<u16>,
+
+    /// Address the HTTP API and web UI bind to
+    #[arg(long, env = "PANDABUTT_ADDRESS")]
+    pub address: Option<IpAddr>,
+
+    /// Nodes only find and sync with nodes sharing the same network id
+    #[arg(long, env = "PANDABUTT_NETWORK_ID")]
+    pub network_id: Option<String>,
+
+    /// Pre-shared secret of a private network, only nodes knowing it derive the same network id
+    #[arg(long, env = "PANDABUTT_NETWORK_SECRET", hide_env_values = true)]
+    pub network_secret: Option<String>,
+
+    /// Peer to connect to on start as `<public key>@<ip>:<port>`, can be given several times
+    #[arg(long, env = "PANDABUTT_BOOTSTRAP", value_delimiter = ',')]
+    pub bootstrap: Vec<PeerAddress>,
+
+    /// UDP port for connections to other nodes, random when not given
+    #[arg(long, env = "PANDABUTT_P2P_PORT")]
+    pub p2p_port: Option<u16>,
+
+    /// Don't discover peers on the local network over mDNS
+    #[arg(long, env = "PANDABUTT_NO_MDNS")]
+    pub no_mdns: bool,
+
+    /// Relay server helping to connect to peers behind NATs, like `https://relay.example.org`
+    #[arg(long, env = "PANDABUTT_RELAY")]
+    pub relay: Option<String>,
+
+    /// Don't use a relay, even when the config file names one
+    #[arg(long, env = "PANDABUTT_NO_RELAY", conflicts_with = "relay")]
+    pub no_relay: bool,
+
+    /// Directory the web UI is served from
+    #[arg(long, env = "PANDABUTT_PUBLIC_DIR")]
+    pub public_dir: Option<PathBuf>,
+
+    /// Further origins browsers may send writes from, like `http://pandabutt.local:8000`
+    #[arg(
+        long = "allow-origin",
+        env = "PANDABUTT_ALLOW_ORIGINS",
+        value_delimiter = ','
+    )]
+    pub allowed_origins: Vec<String>,
+
+    /// Run as an always-online pub without web UI, following back everyone who redeems one of
+    /// its invites
+    #[arg(long = "pub", env = "PANDABUTT_PUB")]
+    pub pub_mode: bool,
+
+    /// How many follows away from us logs are replicated, 1 by default and 2 for pubs
+    #[arg(long, env = "PANDABUTT_HOPS")]
+    pub hops: Option<u8>,
+
+    /// Rebuild the app database from the operation store before starting
+    #[arg(long)]
+    pub reindex: bool,
+}
+
+/// Settings which can be given in the config file, all of them optional
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+struct FileConfig {
+    data_dir: Option<PathBuf>,
+    port: Option<u16>,
+    address: Option<IpAddr>,
+    network_id: Option<String>,
+    network_secret: Option<String>,
+    bootstrap: Option<Vec<PeerAddress>>,
+    p2p_port: Option<u16>,
+    mdns: Option<bool>,
+    relay: Option<String>,
+    public_dir: Option<PathBuf>,
+    allowed_origins: Option<Vec<String>>,
+    #[serde(rename = "pub")]
+    pub_mode: Option<bool>,
+    hops: Option<u8>,
+}
+
+/// Resolved configuration, flags and environment variables take precedence over the config
+/// file which takes precedence over the defaults
+#[derive(Clone, Debug)]
+pub struct Config {
+    pub data_dir: PathBuf,
+    pub port: u16,
+    pub address: IpAddr,
+    pub network_id: String,
+    pub network_secret: Option<String>,
+    pub bootstrap: Vec<PeerAddress>,
+    pub p2p_port: Option<u16>,
+    pub mdns: bool,
+    pub relay: Option<reqwest::Url>,
+    pub pub_mode: bool,
+    pub hops: u8,
+    pub public_dir: PathBuf,
+    pub allowed_origins: Vec<String>,
+}
+
+impl Config {
+    pub fn load(cli: &Cli) -> Result<Self> {
+        let file = FileConfig::load(cli)?;
+        let run = match &cli.command {
+            Command::Run(run) => Some(run),
+            _ => None,
+        };
+
+        let pub_mode = run.is_some_and(|run| run.pub_mode) || file.pub_mode.unwrap_or(false);
+
+        Ok(Config {
+            data_dir: cli
+                .data_dir
+                .clone()
+                .or(file.data_dir)
+                .unwrap_or_else(|| PathBuf::from(DEFAULT_DATA_DIR)),
+            port: run
+                .and_then(|run| run.port)
+                .or(file.port)
+                .unwrap_or(DEFAULT_PORT),
+            address: run
+                .and_then(|run| run.address)
+                .or(file.address)
+                .unwrap_or(IpAddr::V4(Ipv4Addr::LOCALHOST)),
+            network_id: run
+                .and_then(|run| run.network_id.clone())
+                .or(file.network_id)
+                .unwrap_or_else(|| DEFAULT_NETWORK_ID.to_string()),
+            network_secret: run
+                .and_then(|run| run.network_secret.clone())
+                .or(file.network_secret),
+            bootstrap: run
+                .map(|run| run.bootstrap.clone())
+                .filter(|bootstrap| !bootstrap.is_empty())
+                .or(file.bootstrap)
+                .unwrap_or_default(),
+            p2p_port: run.and_then(|run| run.p2p_port).or(file.p2p_port),
+            mdns: !run.is_some_and(|run| run.no_mdns) && file.mdns.unwrap_or(true),
+            relay: run
+                .and_then(|run| run.relay.clone())
+                .or(file.relay)
+                .filter(|_| !run.is_some_and(|run| run.no_relay))
+                .map(|relay| relay.parse().context("invalid relay url"))
+                .transpose()?,
+            pub_mode,
+            hops: run
+                .and_then(|run| run.hops)
+                .or(file.hops)
+                .unwrap_or(if pub_mode {
+                    DEFAULT_PUB_HOPS
+                } else {
+                    DEFAULT_HOPS
+                }),
+            public_dir: run
+                .and_then(|run| run.public_dir.clone())
+                .or(file.public_dir)
+                .unwrap_or_else(|| PathBuf::from(rocket::fs::relative!("public"))),
+            allowed_origins: run
+                .map(|run| run.allowed_origins.clone())
+                .filter(|origins| !origins.is_empty())
+                .or(file.allowed_origins)
+                .unwrap_or_default(),
+        })
+    }
+}
+
+impl Config {
+    /// Id of the network we join. With a secret the id can't be guessed from the network id
+    /// alone, which then only tells apart networks sharing the same secret.
+    pub fn network(&self) -> Hash {
+        let id = Hash::new(self.network_id.as_bytes());
+        match &self.network_secret {
+            Some(secret) => {
+                let mut bytes = id.as_bytes().to_vec();
+                bytes.extend_from_slice(Hash::new(secret.as_bytes()).as_bytes());
+                Hash::new(bytes)
+            }
+            None => id,
+        }
+    }
+}
+
+impl FileConfig {
+    /// An explicitly given config file has to exist, the one in the data directory is optional
+    fn load(cli: &Cli) -> Result<Self> {
+        let path = match &cli.config {
+            Some(path) => path.clone(),
+            None => {
+                let data_dir = cli
+                    .data_dir
+                    .clone()
+                    .unwrap_or_else(|| PathBuf::from(DEFAULT_DATA_DIR));
+                let path = data_dir.join("config.toml");
+                if !path.exists() {
+                    return Ok(FileConfig::default());
+                }
+                path
+            }
+        };
+
+        let contents = fs::read_to_string(&path)
+            .with_context(|| format!("could not read config file {}", path.display()))?;
+        toml::from_str(&contents).with_context(|| format!("invalid config file {}", path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load(args: &[&str]) -> Config {
+        let data_dir = std::env::temp_dir().join("pandabutt-config-test");
+        let mut argv = vec![
+            "panda-butt",
+            "--data-dir",
+            data_dir.to_str().unwrap(),
+            "run",
+        ];
+        argv.extend_from_slice(args);
+        Config::load(&Cli::parse_from(argv)).unwrap()
+    }
+
+    #[test]
+    fn network_depends_on_id_and_secret() {
+        let open = load(&["--network-id", "friends"]).network();
+        assert_eq!(open, Hash::new(b"friends"));
+        assert_ne!(open, load(&["--network-id", "family"]).network());
+
+        let secret = load(&["--network-id", "friends", "--network-secret", "s3cret"]).network();
+        assert_ne!(secret, open);
+        assert_eq!(
+            secret,
+            load(&["--network-id", "friends", "--network-secret", "s3cret"]).network()
+        );
+        assert_ne!(
+            secret,
+            load(&["--network-id", "friends", "--network-secret", "other"]).network()
+        );
+    }
+}
@@ -0,0 +1,85 @@
+// errors of the HTTP API, sent to clients as JSON
+
+use panda_butt::api::ErrorResponse;
+use rocket::http::Status;
+use rocket::response::{self, Responder};
+use rocket::serde::json::Json;
+use rocket::Request;
+use std::fmt;
+
+/// Input which can't be acted on, reported to API clients as a bad request instead of a failure
+/// of the node
+#[derive(Debug)]
+pub struct InvalidInput(pub String);
+
+impl fmt::Display for InvalidInput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for InvalidInput {}
+
+/// Failure of a route together with the status code it is answered with
+#[derive(Debug)]
+pub struct ApiError {
+    status: Status,
+    message: String,
+}
+
+pub type ApiResult<T> = Result<Json<T>, ApiError>;
+
+impl ApiError {
+    pub fn bad_request(message: impl Into<String>) -> Self {
+        ApiError {
+            status: Status::BadRequest,
+            message: message.into(),
+        }
+    }
+
+    pub fn unauthorized(message: impl Into<String>) -> Self {
+        ApiError {
+            status: Status::Unauthorized,
+            message: message.into(),
+        }
+    }
+
+    pub fn not_found(message: impl Into<String>) -> Self {
+        ApiError {
+            status: Status::NotFound,
+            message: message.into(),
+        }
+    }
+}
+
+impl From<anyhow::Error> for ApiError {
+    fn from(err: anyhow::Error) -> Self {
+        if let Some(invalid) = err.downcast_ref::<InvalidInput>() {
+            return ApiError::bad_request(invalid.0.clone());
+        }
+        println!("request failed: {:#}", err);
+        ApiError {
+            status: Status::InternalServerError,
+            message: format!("{:#}", err),
+        }
+    }
+}
+
+impl<'r> Responder<'r, 'static> for ApiError {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'static> {
+        let body = Json(ErrorResponse {
+            error: self.message,
+        });
+        (self.status, body).respond_to(request)
+    }
+}
+
+/// Answers requests Rocket rejects itself, like unknown routes or malformed JSON bodies, in the
+/// same format as errors of our routes
+#[rocket::catch(default)]
+pub fn default_catcher(status: Status, _request: &Request) -> ApiError {
+    ApiError {
+        status,
+        message: status.reason_lossy().to_lowercase(),
+    }
+}
@@ -0,0 +1,185 @@
+// invite codes for meeting a first friend without any pub
+
+use anyhow::{anyhow, bail, Result};
+use p2panda_core::cbor::{decode_cbor, encode_cbor};
+use p2panda_core::{Hash, PrivateKey, PublicKey, Signature};
+use panda_butt::api::LiveEvent;
+use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::Mutex;
+
+use crate::backend::Backend;
+
+const PREFIX: &str = "pandabutt-invite:";
+
+/// Everything an invited node needs to reach and follow the inviter
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Invite {
+    /// Random id the inviter recognizes the invite by once it is redeemed, only holders of the
+    /// code know it
+    pub id: Hash,
+    pub public_key: PublicKey,
+    pub addresses: Vec<SocketAddr>,
+    pub network_id: Hash,
+    /// Whether the inviter follows whoever redeems the invite first
+    pub follow_back: bool,
+}
+
+impl Invite {
+    /// Sign the invite and encode it as text which can be sent around
+    pub fn encode(&self, private_key: &PrivateKey) -> Result<String> {
+        let bytes = encode_cbor(self)?;
+        let signature = private_key.sign(&bytes);
+        let code = encode_cbor(&(bytes, signature))?;
+        let hex: String = code.iter().map(|byte| format!("{:02x}", byte)).collect();
+        Ok(format!("{}{}", PREFIX, hex))
+    }
+
+    /// Fails for malformed codes and for codes not signed by the inviter they name
+    pub fn decode(code: &str) -> Result<Self> {
+        let hex = code
+            .trim()
+            .strip_prefix(PREFIX)
+            .ok_or_else(|| anyhow!("not an invite code"))?;
+        if !hex.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+            bail!("invite code is not hex encoded");
+        }
+        if hex.len() % 2 != 0 {
+            bail!("invite code has an odd length");
+        }
+        let code = (0..hex.len())
+            .step_by(2)
+            .map(|index| u8::from_str_radix(&hex[index..index + 2], 16))
+            .collect::<Result<Vec<u8>, _>>()?;
+
+        let (bytes, signature): (Vec<u8>, Signature) = decode_cbor(&code[..])?;
+        let invite: Invite = decode_cbor(&bytes[..])?;
+        if !invite.public_key.verify(&bytes, &signature) {
+            bail!("invite is not signed by its inviter");
+        }
+        Ok(invite)
+    }
+}
+
+/// What a redeemer publishes instead of the invite id, which has to stay secret because the
+/// redemption is replicated to everyone
+pub fn redemption_proof(invite: &Hash, redeemer: &PublicKey) -> Hash {
+    let mut bytes = invite.as_bytes().to_vec();
+    bytes.extend_from_slice(redeemer.as_bytes());
+    Hash::new(bytes)
+}
+
+/// Follow back everyone who redeemed one of our invites asking for it. Redemptions which came in
+/// while we were not listening, like right before starting, are picked up on the first run.
+pub fn keep_following_back(state: Arc<Mutex<Backend>>) {
+    tokio::task::spawn(async move {
+        let mut rx = state.lock().await.app_data.subscribe();
+        loop {
+            if let Err(err) = state.lock().await.follow_back().await {
+                println!("could not follow back invited peers: {}", err);
+            }
+
+            loop {
+                match rx.recv().await {
+                    // Missed events might have been redemptions as well
+                    Ok(LiveEvent::InviteRedeemed { .. }) | Err(RecvError::Lagged(_)) => break,
+                    Ok(_) => continue,
+                    Err(RecvError::Closed) => return,
+                }
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn invite(public_key: PublicKey) -> Invite {
+        Invite {
+            id: Hash::new(b"invite"),
+            public_key,
+            addresses: vec!["192.168.1.2:2022".parse().unwrap()],
+            network_id: Hash::new(b"network"),
+            follow_back: true,
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let private_key = PrivateKey::new();
+        let code = invite(private_key.public_key())
+            .encode(&private_key)
+            .unwrap();
+
+        let decoded = Invite::decode(&format!("  {}\n", code)).unwrap();
+        assert_eq!(decoded.id, Hash::new(b"invite"));
+        assert_eq!(decoded.public_key, private_key.public_key());
+        assert_eq!(
+            decoded.addresses,
+            invite(private_key.public_key()).addresses
+        );
+        assert_eq!(decoded.network_id, Hash::new(b"network"));
+        assert!(decoded.follow_back);
+    }
+
+    #[test]
+    fn rejects_tampered_code() {
+        let private_key = PrivateKey::new();
+        let code = invite(private_key.public_key())
+            .encode(&private_key)
+            .unwrap();
+
+        // Flip every hex digit in turn, none of the changed codes may decode
+        for index in PREFIX.len()..code.len() {
+            let mut tampered = code.clone().into_bytes();
+            tampered[index] = if tampered[index] == b'0' { b'1' } else { b'0' };
+            let tampered = String::from_utf8(tampered).unwrap();
+            assert!(
+                Invite::decode(&tampered).is_err(),
+                "digit {} changed",
+                index
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_wrong_signer() {
+        let inviter = PrivateKey::new();
+        let code = invite(inviter.public_key())
+            .encode(&PrivateKey::new())
+            .unwrap();
+        assert!(Invite::decode(&code).is_err());
+    }
+
+    #[test]
+    fn proof_is_bound_to_invite_and_redeemer() {
+        let invite = Hash::new(b"invite");
+        let redeemer = PrivateKey::new().public_key();
+
+        let proof = redemption_proof(&invite, &redeemer);
+        assert_eq!(proof, redemption_proof(&invite, &redeemer));
+        assert_ne!(proof, invite);
+        assert_ne!(
+            proof,
+            redemption_proof(&invite, &PrivateKey::new().public_key())
+        );
+        assert_ne!(proof, redemption_proof(&Hash::new(b"other"), &redeemer));
+    }
+
+    #[test]
+    fn rejects_malformed_code() {
+        let private_key = PrivateKey::new();
+        let code = invite(private_key.public_key())
+            .encode(&private_key)
+            .unwrap();
+
+        assert!(Invite::decode(&code[..code.len() - 1]).is_err());
+        assert!(Invite::decode(&code[PREFIX.len()..]).is_err());
+        assert!(Invite::decode(&code.replace(PREFIX, "pandabutt-invitation:")).is_err());
+        assert!(Invite::decode(&format!("{}€0", PREFIX)).is_err());
+        assert!(Invite::decode(&format!("{}+f", PREFIX)).is_err());
+    }
+}
@@ -1,39 +1,100 @@
+mod auth;
 mod backend;
+mod config;
+mod error;
+mod invite;
+mod maintenance;
 mod node;
 mod operation;
+mod peers;
 mod topic;
 mod utils;
 
-use backend::Backend;
+use anyhow::{anyhow, bail, Result};
+use auth::{get_token, ApiAuth, Authorized};
+use backend::{open_stores, AppData, Backend, OperationStore};
+use clap::Parser;
+use config::{Cli, Command, Config};
+use error::{ApiError, ApiResult};
+use operation::{ButtAbout, ButtEvent, ButtExtensions, ButtPostContent};
 use p2panda_core::PrivateKey;
 use p2panda_core::PublicKey;
+use p2panda_core::{Hash, Header};
 use rocket::config::LogLevel;
-use rocket::fs::FileServer;
+use rocket::fs::{FileServer, NamedFile};
+use rocket::http::{Cookie, CookieJar, SameSite};
+use rocket::response::stream::{Event, EventStream};
+use rocket::response::Redirect;
 use rocket::serde::json::Json;
+use rocket::tokio::select;
+use rocket::tokio::sync::broadcast::error::RecvError;
+use rocket::Shutdown;
 use rocket::State;
-use serde::Deserialize;
-use serde::Serialize;
-use std::env;
 use std::fs;
 use std::io::Read;
+use std::net::SocketAddr;
+use std::path::Path;
 use std::sync::Arc;
 use tokio::sync::Mutex;
 
-use crate::backend::FrontendPost;
+use panda_butt::api::{
+    AboutInput, BlockState, CreatedOperation, DeleteInput, EditInput, FollowInput, FollowState,
+    FrontendPost, FrontendRevision, GraphNode, Identity, InviteCode, InviteInput, Member,
+    MemberInput, NodeStatus, PostBodyInput, PostPage, PostsQuery, Profile, ProfileFeed,
+    ReactionInput, ThreadNode, TOKEN_FILE,
+};
 
 #[macro_use]
 extern crate rocket;
 
-#[get("/posts")]
-async fn api_posts(state: &State<Arc<Mutex<Backend>>>) -> Json<Vec<FrontendPost>> {
+#[get("/")]
+async fn index(config: &State<Config>) -> Option<NamedFile> {
+    NamedFile::open(config.public_dir.join("index.html"))
+        .await
+        .ok()
+}
+
+/// Logs the web UI in with the API token, which it keeps in a cookie browsers only send along
+/// with requests from our own pages. Opened through the link the node prints on start.
+#[get("/login?<token>")]
+async fn login(
+    token: &str,
+    auth: &State<ApiAuth>,
+    cookies: &CookieJar<'_>,
+) -> Result<Redirect, ApiError> {
+    if !auth.is_valid(token) {
+        return Err(ApiError::unauthorized("invalid API token"));
+    }
+    let cookie = Cookie::build((auth.cookie().to_string(), auth.token().to_string()))
+        .http_only(true)
+        .same_site(SameSite::Strict)
+        .path("/");
+    cookies.add(cookie);
+    Ok(Redirect::to("/"))
+}
+
+#[get("/posts?<query..>")]
+async fn api_posts(query: PostsQuery, state: &State<Arc<Mutex<Backend>>>) -> ApiResult<PostPage> {
+    let backend = state.lock().await;
+    Ok(Json(backend.app_data.query_posts(&query).await?))
+}
+
+/// Posts of everyone we follow and our own
+#[get("/home?<query..>")]
+async fn api_home(query: PostsQuery, state: &State<Arc<Mutex<Backend>>>) -> ApiResult<PostPage> {
     let backend = state.lock().await;
-    let posts = backend.app_data.get_posts().await;
-    Json(posts)
+    Ok(Json(backend.app_data.get_home_feed(&query).await?))
 }
 
-#[derive(Serialize)]
-struct Identity {
-    public_key: PublicKey,
+/// Root post of a thread with its replies nested under the posts they answer
+#[get("/thread/<id>")]
+async fn api_thread(id: &str, state: &State<Arc<Mutex<Backend>>>) -> ApiResult<ThreadNode> {
+    let root = parse_hash(id)?;
+    let backend = state.lock().await;
+    match backend.app_data.get_thread(&root).await {
+        Some(thread) => Ok(Json(thread)),
+        None => Err(ApiError::not_found(format!("unknown post {}", root))),
+    }
 }
 
 #[get("/id")]
@@ -44,74 +105,528 @@ async fn api_id(state: &State<Arc<Mutex<Backend>>>) -> Json<Identity> {
     })
 }
 
-#[derive(Deserialize, Debug)]
-struct PostBodyInput {
-    body: String,
+/// Our network id, relay and the addresses other nodes can reach us at
+#[get("/status")]
+async fn api_status(state: &State<Arc<Mutex<Backend>>>) -> Json<NodeStatus> {
+    let backend = state.lock().await;
+    Json(backend.status().await)
 }
 
 #[post("/post", data = "<input>")]
 async fn api_make_post(
+    _auth: Authorized,
     input: Json<PostBodyInput>,
     state: &State<Arc<Mutex<Backend>>>,
-) -> &'static str {
+) -> ApiResult<CreatedOperation<FrontendPost>> {
     let mut backend = state.lock().await;
 
-    backend.create_post(input.body.to_owned()).await;
-    "created a new post"
+    let input = input.into_inner();
+    let (_, header) = backend
+        .create_post(ButtPostContent {
+            body: input.body,
+            root: input.root,
+            parent: input.parent,
+        })
+        .await?;
+    let post = get_post_view(&backend, &header.hash()).await?;
+    Ok(created(&header, post))
 }
 
-#[launch]
-async fn main2() -> _ {
-    let args: Vec<String> = env::args().collect();
+/// Returns the reacted to post, unless we don't have it
+#[post("/react", data = "<input>")]
+async fn api_react(
+    _auth: Authorized,
+    input: Json<ReactionInput>,
+    state: &State<Arc<Mutex<Backend>>>,
+) -> ApiResult<CreatedOperation<Option<FrontendPost>>> {
+    let mut backend = state.lock().await;
 
-    let name = args.get(1).expect("Needs name argument (position 1)");
-    println!("starting node for 'name' {}", name);
+    let input = input.into_inner();
+    let (_, header) = backend.react(input.target, input.reaction).await?;
+    let post = backend.app_data.get_post(&input.target).await;
+    Ok(created(&header, post))
+}
 
-    let data_directory = format!("./keys/{}", name);
-    let private_key = get_key(&data_directory);
+/// Returns the post the reaction was retracted from, unless we don't have it
+#[post("/unreact", data = "<input>")]
+async fn api_unreact(
+    _auth: Authorized,
+    input: Json<ReactionInput>,
+    state: &State<Arc<Mutex<Backend>>>,
+) -> ApiResult<CreatedOperation<Option<FrontendPost>>> {
+    let mut backend = state.lock().await;
 
-    println!("key {}", private_key);
+    let input = input.into_inner();
+    let (_, header) = backend.unreact(input.target, input.reaction).await?;
+    let post = backend.app_data.get_post(&input.target).await;
+    Ok(created(&header, post))
+}
 
-    let backend = Backend::new(private_key, data_directory)
-        .await
-        .expect("backend up be startable");
-    let state = Arc::new(Mutex::new(backend));
+/// Returns the edited post
+#[post("/edit", data = "<input>")]
+async fn api_edit_post(
+    _auth: Authorized,
+    input: Json<EditInput>,
+    state: &State<Arc<Mutex<Backend>>>,
+) -> ApiResult<CreatedOperation<FrontendPost>> {
+    let mut backend = state.lock().await;
+
+    let input = input.into_inner();
+    let (_, header) = backend.edit_post(input.target, input.new_body).await?;
+    let post = get_post_view(&backend, &input.target).await?;
+    Ok(created(&header, post))
+}
+
+/// Returns the deleted post, which is kept as a tombstone without its text
+#[post("/delete", data = "<input>")]
+async fn api_delete_post(
+    _auth: Authorized,
+    input: Json<DeleteInput>,
+    state: &State<Arc<Mutex<Backend>>>,
+) -> ApiResult<CreatedOperation<FrontendPost>> {
+    let mut backend = state.lock().await;
+
+    let (_, header) = backend.delete_post(input.target).await?;
+    let post = get_post_view(&backend, &input.target).await?;
+    Ok(created(&header, post))
+}
+
+#[get("/revisions/<id>")]
+async fn api_revisions(
+    id: &str,
+    state: &State<Arc<Mutex<Backend>>>,
+) -> ApiResult<Vec<FrontendRevision>> {
+    let target = parse_hash(id)?;
+    let backend = state.lock().await;
+    Ok(Json(backend.app_data.get_revisions(&target).await))
+}
+
+#[post("/block", data = "<input>")]
+async fn api_block(
+    _auth: Authorized,
+    input: Json<FollowInput>,
+    state: &State<Arc<Mutex<Backend>>>,
+) -> ApiResult<CreatedOperation<BlockState>> {
+    let mut backend = state.lock().await;
+
+    let (_, header) = backend.block(input.public_key).await?;
+    let block = get_block_view(&backend, input.public_key);
+    Ok(created(&header, block))
+}
+
+#[post("/unblock", data = "<input>")]
+async fn api_unblock(
+    _auth: Authorized,
+    input: Json<FollowInput>,
+    state: &State<Arc<Mutex<Backend>>>,
+) -> ApiResult<CreatedOperation<BlockState>> {
+    let mut backend = state.lock().await;
+
+    let (_, header) = backend.unblock(input.public_key).await?;
+    let block = get_block_view(&backend, input.public_key);
+    Ok(created(&header, block))
+}
+
+#[post("/follow", data = "<input>")]
+async fn api_follow(
+    _auth: Authorized,
+    input: Json<FollowInput>,
+    state: &State<Arc<Mutex<Backend>>>,
+) -> ApiResult<CreatedOperation<FollowState>> {
+    let mut backend = state.lock().await;
+
+    let (_, header) = backend.follow(input.public_key).await?;
+    let follow = get_follow_view(&backend, input.public_key).await;
+    Ok(created(&header, follow))
+}
+
+#[post("/unfollow", data = "<input>")]
+async fn api_unfollow(
+    _auth: Authorized,
+    input: Json<FollowInput>,
+    state: &State<Arc<Mutex<Backend>>>,
+) -> ApiResult<CreatedOperation<FollowState>> {
+    let mut backend = state.lock().await;
+
+    let (_, header) = backend.unfollow(input.public_key).await?;
+    let follow = get_follow_view(&backend, input.public_key).await;
+    Ok(created(&header, follow))
+}
+
+/// Signed invite code for someone to reach and follow us. Invites of a pub can be redeemed by
+/// anyone holding them and always follow back.
+#[post("/invite", data = "<input>")]
+async fn api_invite(
+    _auth: Authorized,
+    input: Json<InviteInput>,
+    config: &State<Config>,
+    state: &State<Arc<Mutex<Backend>>>,
+) -> ApiResult<InviteCode> {
+    let mut backend = state.lock().await;
+
+    let code = if config.pub_mode {
+        backend.create_invite(true, None).await?
+    } else {
+        backend.create_invite(input.follow_back, Some(1)).await?
+    };
+    Ok(Json(InviteCode { code }))
+}
+
+/// Follows the inviter and returns the redemption operation
+#[post("/redeem", data = "<input>")]
+async fn api_redeem(
+    _auth: Authorized,
+    input: Json<InviteCode>,
+    state: &State<Arc<Mutex<Backend>>>,
+) -> ApiResult<CreatedOperation<FollowState>> {
+    let mut backend = state.lock().await;
+
+    let (event, header) = backend.redeem_invite(&input.code).await?;
+    let ButtEvent::RedeemInvite { inviter, .. } = event else {
+        unreachable!("redeeming an invite creates a redeem event");
+    };
+    let follow = get_follow_view(&backend, inviter).await;
+    Ok(created(&header, follow))
+}
+
+/// Everyone who redeemed one of our invites
+#[get("/admin/members")]
+async fn api_members(
+    _auth: Authorized,
+    state: &State<Arc<Mutex<Backend>>>,
+) -> ApiResult<Vec<Member>> {
+    let backend = state.lock().await;
+    Ok(Json(backend.app_data.get_members().await?))
+}
+
+/// Unfollows and blocks a member, returning the block operation
+#[post("/admin/revoke", data = "<input>")]
+async fn api_revoke(
+    _auth: Authorized,
+    input: Json<MemberInput>,
+    state: &State<Arc<Mutex<Backend>>>,
+) -> ApiResult<CreatedOperation<Member>> {
+    let mut backend = state.lock().await;
+
+    let (_, header) = backend.revoke_member(input.public_key).await?;
+    let member = backend.app_data.get_member(&input.public_key).await;
+    Ok(created(&header, member))
+}
+
+/// Keys the given key follows
+#[get("/following/<key>")]
+async fn api_following(key: &str, state: &State<Arc<Mutex<Backend>>>) -> ApiResult<Vec<PublicKey>> {
+    let public_key = parse_key(key)?;
+    let backend = state.lock().await;
+    Ok(Json(backend.app_data.get_following(&public_key).await))
+}
+
+/// Keys following the given key
+#[get("/followers/<key>")]
+async fn api_followers(key: &str, state: &State<Arc<Mutex<Backend>>>) -> ApiResult<Vec<PublicKey>> {
+    let public_key = parse_key(key)?;
+    let backend = state.lock().await;
+    Ok(Json(backend.app_data.get_followers(&public_key).await))
+}
+
+/// Every known key with its hop distance from us and the follows connecting us
+#[get("/graph")]
+async fn api_graph(state: &State<Arc<Mutex<Backend>>>) -> Json<Vec<GraphNode>> {
+    let backend = state.lock().await;
+    Json(backend.app_data.get_graph().await)
+}
+
+#[get("/profile/<key>")]
+async fn api_profile(key: &str, state: &State<Arc<Mutex<Backend>>>) -> ApiResult<Profile> {
+    let public_key = parse_key(key)?;
+    let backend = state.lock().await;
+    Ok(Json(backend.app_data.get_profile(&public_key).await))
+}
+
+/// Publish our own profile, returning it with the new fields applied
+#[post("/about", data = "<input>")]
+async fn api_about(
+    _auth: Authorized,
+    input: Json<AboutInput>,
+    state: &State<Arc<Mutex<Backend>>>,
+) -> ApiResult<CreatedOperation<Profile>> {
+    let mut backend = state.lock().await;
 
-    let port = match name.as_str() {
-        "a" => 8000,
-        _ => 9000,
+    let input = input.into_inner();
+    let about = ButtAbout {
+        name: input.name,
+        description: input.description,
+        avatar: input.avatar,
     };
+    let (_, header) = backend.update_about(about).await?;
+    let profile = backend.app_data.get_profile(&header.public_key).await;
+    Ok(created(&header, profile))
+}
+
+/// Someone's profile together with a page of their posts
+#[get("/profile/<key>/feed?<query..>")]
+async fn api_profile_feed(
+    key: &str,
+    query: PostsQuery,
+    state: &State<Arc<Mutex<Backend>>>,
+) -> ApiResult<ProfileFeed> {
+    let public_key = parse_key(key)?;
+    let backend = state.lock().await;
+    Ok(Json(
+        backend
+            .app_data
+            .get_profile_feed(&public_key, &query)
+            .await?,
+    ))
+}
+
+/// Server-sent stream of new posts, follows, profile changes and peer activity
+#[get("/events")]
+async fn api_events(state: &State<Arc<Mutex<Backend>>>, mut end: Shutdown) -> EventStream![] {
+    let mut rx = state.lock().await.app_data.subscribe();
+    EventStream! {
+        loop {
+            let event = select! {
+                event = rx.recv() => match event {
+                    Ok(event) => event,
+                    Err(RecvError::Closed) => break,
+                    Err(RecvError::Lagged(_)) => continue,
+                },
+                _ = &mut end => break,
+            };
+            yield Event::json(&event);
+        }
+    }
+}
+
+/// Returns our profile, which is all the pruning operation carries
+#[post("/prune")]
+async fn api_prune(
+    _auth: Authorized,
+    state: &State<Arc<Mutex<Backend>>>,
+) -> ApiResult<CreatedOperation<Profile>> {
+    let mut backend = state.lock().await;
+
+    let (_, header) = backend.prune_history().await?;
+    let profile = backend.app_data.get_profile(&header.public_key).await;
+    Ok(created(&header, profile))
+}
+
+fn created<T>(header: &Header<ButtExtensions>, view: T) -> Json<CreatedOperation<T>> {
+    Json(CreatedOperation {
+        hash: header.hash(),
+        seq_num: header.seq_num,
+        timestamp: header.timestamp,
+        public_key: header.public_key,
+        view,
+    })
+}
+
+/// Our own posts are materialized before a write returns, so a missing one means
+/// materialization failed
+async fn get_post_view(backend: &Backend, id: &Hash) -> Result<FrontendPost, ApiError> {
+    match backend.app_data.get_post(id).await {
+        Some(post) => Ok(post),
+        None => Err(anyhow!("post {} was stored but could not be materialized", id).into()),
+    }
+}
+
+async fn get_follow_view(backend: &Backend, target: PublicKey) -> FollowState {
+    let following = backend
+        .app_data
+        .get_following(&backend.private_key.public_key())
+        .await
+        .contains(&target);
+    FollowState { target, following }
+}
+
+fn get_block_view(backend: &Backend, target: PublicKey) -> BlockState {
+    BlockState {
+        target,
+        blocked: backend.app_data.is_blocked(&target),
+    }
+}
+
+fn parse_key(key: &str) -> Result<PublicKey, ApiError> {
+    key.parse()
+        .map_err(|_| ApiError::bad_request(format!("invalid public key {}", key)))
+}
+
+fn parse_hash(hash: &str) -> Result<Hash, ApiError> {
+    hash.parse()
+        .map_err(|_| ApiError::bad_request(format!("invalid hash {}", hash)))
+}
+
+#[rocket::main]
+async fn main() -> Result<()> {
+    let cli = Cli::parse();
+    let config = Config::load(&cli)?;
+
+    match cli.command {
+        Command::Run(run) => run_node(config, run.reindex).await?,
+        Command::Init => {
+            let private_key = get_key(&config.data_dir);
+            get_token(&config.data_dir)?;
+            println!("{}", private_key.public_key());
+        }
+        Command::Id => {
+            let private_key = read_key(&config.data_dir).ok_or_else(|| no_key(&config.data_dir))?;
+            println!("{}", private_key.public_key());
+        }
+        Command::Export { path } => {
+            let (store, app_data) = open_data_dir(&config, false).await?;
+            let exported = maintenance::export(&store, &app_data, &path).await?;
+            println!("exported {} operations to {}", exported, path.display());
+        }
+        Command::Import { path } => {
+            let (mut store, app_data) = open_data_dir(&config, false).await?;
+            let imported = maintenance::import(&mut store, &app_data, &path).await?;
+            println!("imported {} new operations", imported);
+        }
+        Command::Reindex => {
+            open_data_dir(&config, true).await?;
+        }
+        Command::Verify => {
+            let (store, app_data) = open_data_dir(&config, false).await?;
+            let problems = maintenance::verify(&store, &app_data).await?;
+            if problems > 0 {
+                bail!("found {} problems in the operation store", problems);
+            }
+            println!("all operations verified");
+        }
+    }
+
+    Ok(())
+}
+
+/// Nodes used to keep their key in `./keys/<name>`, point their owners there instead of letting
+/// them start over with a new identity
+fn no_key(dir: &Path) -> anyhow::Error {
+    let legacy = Path::new("./keys");
+    if legacy.is_dir() {
+        anyhow!(
+            "no key found in {}, keys of earlier versions are in {}: pass one of them with \
+             `--data-dir {}/<name>` or run `init` for a new identity",
+            dir.display(),
+            legacy.display(),
+            legacy.display()
+        )
+    } else {
+        anyhow!("no key found in {}, run `init` first", dir.display())
+    }
+}
+
+async fn run_node(config: Config, force_reindex: bool) -> Result<()> {
+    println!("starting node in {}", config.data_dir.display());
+    let private_key = read_key(&config.data_dir).ok_or_else(|| no_key(&config.data_dir))?;
+
+    println!("key {}", private_key);
+
+    let auth = ApiAuth::new(get_token(&config.data_dir)?, &config);
+    println!(
+        "API token in {}",
+        config.data_dir.join(TOKEN_FILE).display()
+    );
+
+    if !config.pub_mode {
+        println!(
+            "open http://{}/login?token={} to use the web UI",
+            SocketAddr::new(config.address, config.port),
+            auth.token()
+        );
+    }
+
+    let backend = Backend::new(private_key, &config, force_reindex).await?;
+    let state = Arc::new(Mutex::new(backend));
+    invite::keep_following_back(state.clone());
 
-    rocket::build()
+    let mut rocket = rocket::build()
         .manage(state)
+        .manage(auth)
+        .manage(config.clone())
         .configure(
             rocket::Config::figment()
-                .merge(("port", port))
+                .merge(("address", config.address))
+                .merge(("port", config.port))
                 .merge(("log_level", LogLevel::Critical)),
         )
-        .mount("/", FileServer::new("public", rocket::fs::Options::Index))
-        .mount("/", routes![api_id, api_posts, api_make_post])
+        .mount(
+            "/",
+            routes![
+                api_id,
+                api_status,
+                api_posts,
+                api_home,
+                api_thread,
+                api_make_post,
+                api_react,
+                api_unreact,
+                api_edit_post,
+                api_delete_post,
+                api_revisions,
+                api_follow,
+                api_unfollow,
+                api_block,
+                api_unblock,
+                api_invite,
+                api_redeem,
+                api_following,
+                api_followers,
+                api_graph,
+                api_profile,
+                api_profile_feed,
+                api_about,
+                api_events,
+                api_prune
+            ],
+        )
+        .register("/", catchers![error::default_catcher]);
+
+    // A pub only replicates for its members, it is administered through the API
+    if config.pub_mode {
+        println!("running as a pub, the web UI is disabled");
+        rocket = rocket.mount("/", routes![api_members, api_revoke]);
+    } else {
+        rocket = rocket
+            .mount(
+                "/",
+                FileServer::new(&config.public_dir, rocket::fs::Options::Index),
+            )
+            .mount("/", routes![index, login]);
+    }
+
+    let result = rocket.launch().await;
 
-    // tokio::signal::ctrl_c().await.unwrap();
+    if let Err(err) = result {
+        bail!("web server stopped: {}", err);
+    }
+    Ok(())
+}
+
+/// Databases of a node which is not running, for the maintenance commands
+async fn open_data_dir(config: &Config, force_reindex: bool) -> Result<(OperationStore, AppData)> {
+    let private_key = read_key(&config.data_dir).ok_or_else(|| no_key(&config.data_dir))?;
+    open_stores(&config.data_dir, private_key.public_key(), force_reindex).await
 }
 
-fn get_key(dir: &String) -> PrivateKey {
-    let _ = fs::DirBuilder::new().create(dir);
-    let file_path = format!("{}/key", dir);
+fn read_key(dir: &Path) -> Option<PrivateKey> {
+    let mut file = fs::File::open(dir.join("key")).ok()?;
+    let mut bytes = [0u8; 32];
+    file.read_exact(&mut bytes).ok()?;
+    Some(PrivateKey::from_bytes(&bytes))
+}
 
-    if let Ok(mut file) = fs::File::open(&file_path) {
-        let mut bytes = [0u8; 32];
+fn get_key(dir: &Path) -> PrivateKey {
+    let _ = fs::create_dir_all(dir);
 
-        if let Ok(()) = file.read_exact(&mut bytes) {
-            println!("found a key");
-            // found a key and read it into our byes
-            return PrivateKey::from_bytes(&bytes);
-        };
+    if let Some(private_key) = read_key(dir) {
+        println!("found a key");
+        return private_key;
     }
 
     println!("did not find a key! creating a key");
     // create one!
     let new_key = PrivateKey::new();
-    let _ = fs::write(&file_path, new_key.as_bytes());
+    let _ = fs::write(dir.join("key"), new_key.as_bytes());
     new_key
 }
@@ -0,0 +1,120 @@
+// exporting, importing and verifying the operation store
+
+use anyhow::{bail, Result};
+use p2panda_core::cbor::{decode_cbor, encode_cbor};
+use p2panda_core::{Body, Header};
+use p2panda_store::{LocalOperationStore, LogStore};
+use std::fs;
+use std::path::Path;
+
+use crate::backend::{get_log_authors, process_operation, AppData, ButtLogId, OperationStore};
+use crate::operation::ButtExtensions;
+
+/// Header and body bytes of an operation, the format of an export file is a list of these
+type RawOperation = (Vec<u8>, Option<Vec<u8>>);
+
+/// Write every log to `path` in seq_num order, returning how many operations were written
+pub async fn export(store: &OperationStore, app_data: &AppData, path: &Path) -> Result<usize> {
+    let mut operations: Vec<RawOperation> = vec![];
+    for author in get_log_authors(&app_data.pool).await? {
+        if let Some(log) = store.get_raw_log(&author, &ButtLogId(author), None).await? {
+            operations.extend(log);
+        }
+    }
+
+    fs::write(path, encode_cbor(&operations)?)?;
+    Ok(operations.len())
+}
+
+/// Store and materialize the operations of an export file which we don't have yet, returning
+/// how many were added. Operations failing their signature or payload check are skipped.
+pub async fn import(store: &mut OperationStore, app_data: &AppData, path: &Path) -> Result<usize> {
+    let bytes = fs::read(path)?;
+    let operations: Vec<RawOperation> = decode_cbor(&bytes[..])?;
+
+    let mut imported = 0;
+    for (header_bytes, body_bytes) in operations {
+        let header: Header<ButtExtensions> = decode_cbor(&header_bytes[..])?;
+        let body = body_bytes.map(|bytes| Body::new(&bytes));
+
+        if let Err(err) = check_operation(&header, body.as_ref()) {
+            println!("skipping operation {}: {}", header.hash(), err);
+            continue;
+        }
+        if store.has_operation(header.hash()).await? {
+            continue;
+        }
+
+        store
+            .insert_operation(
+                header.hash(),
+                &header,
+                body.as_ref(),
+                &header_bytes,
+                &ButtLogId(header.public_key),
+            )
+            .await?;
+        if let Some(body) = &body {
+            process_operation(store, app_data, &header, body).await?;
+        }
+        imported += 1;
+    }
+
+    Ok(imported)
+}
+
+/// Check every log for bad signatures, payloads not matching their header and broken backlinks,
+/// printing each problem found and returning how many there were
+pub async fn verify(store: &OperationStore, app_data: &AppData) -> Result<usize> {
+    let mut problems = 0;
+    for author in get_log_authors(&app_data.pool).await? {
+        let log = store
+            .get_log(&author, &ButtLogId(author), None)
+            .await?
+            .unwrap_or_default();
+
+        let mut previous: Option<&Header<ButtExtensions>> = None;
+        for (header, body) in &log {
+            let mut issues = vec![];
+            if let Err(err) = check_operation(header, body.as_ref()) {
+                issues.push(err.to_string());
+            }
+            // Pruned logs start later than zero, so only links between neighbours are checked
+            if let Some(previous) = previous {
+                if header.seq_num != previous.seq_num + 1 {
+                    issues.push(format!("seq_num follows {}", previous.seq_num));
+                } else if header.backlink != Some(previous.hash()) {
+                    issues.push("backlink does not point at the previous operation".to_string());
+                }
+            }
+
+            for issue in &issues {
+                println!(
+                    "{} #{} {}: {}",
+                    author,
+                    header.seq_num,
+                    header.hash(),
+                    issue
+                );
+            }
+            problems += issues.len();
+            previous = Some(header);
+        }
+    }
+
+    Ok(problems)
+}
+
+/// Checks an operation has to pass before we accept it from somewhere other than the network,
+/// bodies may be missing for deleted posts
+fn check_operation(header: &Header<ButtExtensions>, body: Option<&Body>) -> Result<()> {
+    if !header.verify() {
+        bail!("invalid signature");
+    }
+    if let Some(body) = body {
+        if header.payload_hash != Some(body.hash()) || header.payload_size != body.size() {
+            bail!("payload does not match its header");
+        }
+    }
+    Ok(())
+}
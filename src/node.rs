@@ -1,27 +1,36 @@
 // handle all the networking, sync and operation ingest
 
 use anyhow::Result;
-use p2panda_core::{cbor::decode_cbor, Body, Hash, Header, PrivateKey};
+use iroh::watcher::Watcher;
+use p2panda_core::{cbor::decode_cbor, Body, Hash, Header, PrivateKey, PublicKey};
 use p2panda_discovery::mdns::LocalDiscovery;
 use p2panda_net::{FromNetwork, Network, NetworkBuilder, SyncConfiguration, ToNetwork};
 use p2panda_stream::{DecodeExt, IngestExt};
 use p2panda_sync::log_sync::LogSyncProtocol;
 
+use panda_butt::api::{LiveEvent, NodeStatus, PeerStatus};
 use tokio::sync::mpsc::{self, Sender};
 use tokio::task;
 use tokio_stream::wrappers::ReceiverStream;
 use tokio_stream::StreamExt;
 
 use crate::{
-    backend::OperationStore,
+    backend::{AppData, OperationStore},
+    config::Config,
     operation::{encode_gossip_operation, ButtExtensions},
+    peers::{AddressBook, PeerAddress},
     topic::{ButtLogMap, ButtQuery},
 };
 
+/// STUN port of iroh relay servers, for discovering our public address
+const RELAY_STUN_PORT: u16 = 3478;
+
 pub struct ButtNode {
-    #[allow(unused)]
     network: Network<ButtQuery>,
     gossip_tx: Sender<ToNetwork>,
+    public_key: PublicKey,
+    network_id: Hash,
+    relay: Option<reqwest::Url>,
 }
 
 impl ButtNode {
@@ -30,14 +39,40 @@ impl ButtNode {
         private_key: PrivateKey,
         backend_tx: mpsc::Sender<(Header<ButtExtensions>, Body)>,
         topic_map: ButtLogMap,
+        app_data: AppData,
+        config: &Config,
     ) -> Self {
-        let mdns = LocalDiscovery::new();
+        let network_id = config.network();
+        let mut builder = NetworkBuilder::new(network_id.into()).private_key(private_key.clone());
+
+        if config.mdns {
+            builder = builder.discovery(LocalDiscovery::new());
+        }
+        if let Some(port) = config.p2p_port {
+            builder = builder.bind_port_v4(port);
+        }
+
+        // Dial configured bootstrap peers and everyone we met before directly
+        let mut address_book = AddressBook::load(&config.data_dir);
+        for peer in &config.bootstrap {
+            address_book.add(peer.clone());
+        }
+        for peer in address_book.peers() {
+            if peer.public_key != private_key.public_key() {
+                builder = builder.direct_address(peer.public_key, peer.addresses, None);
+            }
+        }
+
+        if let Some(relay) = &config.relay {
+            println!("using relay {}", relay);
+            let url = relay
+                .as_str()
+                .parse()
+                .expect("relay url checked by the config");
+            builder = builder.relay(url, false, RELAY_STUN_PORT);
+        }
 
-        let network = NetworkBuilder::new(Hash::new(b"butt-net").into())
-            .private_key(private_key)
-            .discovery(mdns)
-            // .direct_address(bootstrap_public_key, vec![], None)
-            // .relay("https://wasser.liebechaos.org".parse().unwrap(), false, 0)
+        let network = builder
             .sync(SyncConfiguration::new(LogSyncProtocol::new(
                 topic_map,
                 store.clone(),
@@ -45,27 +80,51 @@ impl ButtNode {
             .build()
             .await
             .unwrap();
+        address_book.keep_saving(network.clone());
 
-        let (gossip_tx, rx, gossip_ready) = network.subscribe(ButtQuery { hops: 1 }).await.unwrap();
+        let (gossip_tx, rx, gossip_ready) = network
+            .subscribe(ButtQuery {
+                network: network_id,
+                hops: config.hops,
+            })
+            .await
+            .unwrap();
 
         let backend_copy = backend_tx.clone();
+        let peer_app_data = app_data.clone();
+        let ready_app_data = app_data.clone();
         task::spawn(async move {
             let stream = ReceiverStream::new(rx);
-            let stream = stream.filter_map(|event| match event {
-                FromNetwork::GossipMessage { bytes, .. } => match decode_gossip_message(&bytes) {
-                    Ok(result) => {
-                        println!("got gossip message in! 👂");
-                        Some(result)
-                    }
-                    Err(err) => {
-                        error!("could not decode gossip message: {err}");
-                        None
+            let stream = stream.filter_map(move |event| match event {
+                FromNetwork::GossipMessage {
+                    bytes,
+                    delivered_from,
+                } => {
+                    peer_app_data.publish(LiveEvent::Peer {
+                        public_key: Some(delivered_from),
+                        status: PeerStatus::Gossiped,
+                    });
+                    match decode_gossip_message(&bytes) {
+                        Ok(result) => {
+                            println!("got gossip message in! 👂");
+                            Some(result)
+                        }
+                        Err(err) => {
+                            error!("could not decode gossip message: {err}");
+                            None
+                        }
                     }
-                },
+                }
                 FromNetwork::SyncMessage {
-                    header, payload, ..
+                    header,
+                    payload,
+                    delivered_from,
                 } => {
                     println!("got sync message in!");
+                    peer_app_data.publish(LiveEvent::Peer {
+                        public_key: Some(delivered_from),
+                        status: PeerStatus::Synced,
+                    });
                     Some((header, payload))
                 }
             });
@@ -83,6 +142,14 @@ impl ButtNode {
                         None
                     }
                 })
+                .filter(move |operation| {
+                    // Drop anything from authors we blocked before it reaches the store
+                    let blocked = app_data.is_blocked(&operation.header.public_key);
+                    if blocked {
+                        println!("dropping operation from blocked author 🚫");
+                    }
+                    !blocked
+                })
                 .ingest(store.clone(), 128)
                 .filter_map(|result| match result {
                     Ok(operation) => Some(operation),
@@ -95,35 +162,78 @@ impl ButtNode {
             tokio::task::spawn(async move {
                 // Process the operations and forward application messages to app layer.
                 while let Some(operation) = stream.next().await {
-                    // Forward the payload up to the app.
+                    // Forward the payload up to the app, deleted posts arrive without one
+                    let Some(body) = operation.body else {
+                        println!("operation arrived without a body, nothing to materialize");
+                        continue;
+                    };
                     println!("sending operation to the app backend");
-                    let _r = backend_copy
-                        .send((
-                            operation.header,
-                            operation.body.expect("all operations have bodies"),
-                        ))
-                        .await;
+                    let _r = backend_copy.send((operation.header, body)).await;
                 }
             });
         });
 
-        task::spawn(async {
+        task::spawn(async move {
             if gossip_ready.await.is_ok() {
                 println!("met a peer, ready to gossip");
+                ready_app_data.publish(LiveEvent::Peer {
+                    public_key: None,
+                    status: PeerStatus::GossipReady,
+                });
             }
         });
 
-        ButtNode { network, gossip_tx }
+        ButtNode {
+            network,
+            gossip_tx,
+            public_key: private_key.public_key(),
+            network_id,
+            relay: config.relay.clone(),
+        }
+    }
+
+    /// Where other nodes can reach us
+    pub async fn status(&self) -> NodeStatus {
+        NodeStatus {
+            public_key: self.public_key,
+            network_id: self.network_id,
+            relay: self
+                .network
+                .endpoint()
+                .home_relay()
+                .get()
+                .ok()
+                .flatten()
+                .map(|relay| relay.to_string()),
+            configured_relay: self.relay.as_ref().map(|relay| relay.to_string()),
+            direct_addresses: self.network.direct_addresses().await.unwrap_or_default(),
+        }
+    }
+
+    /// Dial a peer we just learned about instead of waiting for discovery
+    pub async fn add_peer(&self, peer: &PeerAddress) -> Result<()> {
+        let node_id = iroh::PublicKey::from_bytes(peer.public_key.as_bytes())?;
+        let node_addr = iroh::NodeAddr::from_parts(node_id, None, peer.addresses.clone());
+        self.network.add_peer(node_addr).await
     }
 
     pub async fn send_gossip(&self, header: Header<ButtExtensions>, body: Body) {
-        let encoded = encode_gossip_operation(header.clone(), Some(body.clone())).unwrap();
+        let encoded = match encode_gossip_operation(header.clone(), Some(body.clone())) {
+            Ok(encoded) => encoded,
+            Err(err) => {
+                println!("could not encode operation {}: {}", header.hash(), err);
+                return;
+            }
+        };
         let message = ToNetwork::Message { bytes: encoded };
         println!(
             "gossiping about a new operation to my friends {}",
             header.hash()
         );
-        let _ = self.gossip_tx.send(message).await;
+        // Peers which miss the gossip still get the operation on their next sync
+        if let Err(err) = self.gossip_tx.send(message).await {
+            println!("could not gossip operation {}: {}", header.hash(), err);
+        }
     }
 }
 
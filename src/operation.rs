@@ -4,6 +4,7 @@ use p2panda_core::cbor::encode_cbor;
 use p2panda_core::{Body, Extension, Extensions, Header, PruneFlag};
 use p2panda_core::{Hash, PublicKey};
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use std::hash::Hash as StdHash;
 
 #[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
@@ -42,18 +43,146 @@ pub struct ButtPost {
     pub body: String,
 }
 
+/// Self-identification for a user, fields left as `None` keep their previous value
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct ButtAbout {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    /// base64 encoded image
+    pub avatar: Option<String>,
+}
+
+/// Text of a post, optionally replying within a thread. `root` is the first post of the thread
+/// and `parent` the post being replied to, both are the `header.hash()` of those operations.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(from = "PostFormat", into = "PostFormat")]
+pub struct ButtPostContent {
+    pub body: String,
+    pub root: Option<Hash>,
+    pub parent: Option<Hash>,
+}
+
+/// Wire format of a post, posts without thread references stay a bare string like they always
+/// were so older peers can still read them
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum PostFormat {
+    Text(String),
+    Reply {
+        body: String,
+        root: Option<Hash>,
+        parent: Option<Hash>,
+    },
+}
+
+impl From<PostFormat> for ButtPostContent {
+    fn from(format: PostFormat) -> Self {
+        match format {
+            PostFormat::Text(body) => ButtPostContent {
+                body,
+                root: None,
+                parent: None,
+            },
+            PostFormat::Reply { body, root, parent } => ButtPostContent { body, root, parent },
+        }
+    }
+}
+
+impl From<ButtPostContent> for PostFormat {
+    fn from(post: ButtPostContent) -> Self {
+        match post {
+            ButtPostContent {
+                body,
+                root: None,
+                parent: None,
+            } => PostFormat::Text(body),
+            ButtPostContent { body, root, parent } => PostFormat::Reply { body, root, parent },
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize)]
 pub enum ButtEvent {
-    Post(String),
+    Post(ButtPostContent),
     Follow(PublicKey),
+    Unfollow(PublicKey),
+    About(ButtAbout),
+    Block(PublicKey),
+    Unblock(PublicKey),
+    React {
+        target: Hash,
+        reaction: String,
+    },
+    Unreact {
+        target: Hash,
+        reaction: String,
+    },
+    Edit {
+        target: Hash,
+        new_body: String,
+    },
+    Delete {
+        target: Hash,
+    },
+    /// Redemption of an invite, tells the inviter who used it. The proof binds the invite's
+    /// secret id to the redeeming author, so a copied redemption proves nothing.
+    RedeemInvite {
+        inviter: PublicKey,
+        proof: Hash,
+    },
+    /// Event type published by some other client which we don't know, kept but never rendered
+    #[serde(skip)]
+    Unknown {
+        event_type: String,
+        raw: Vec<u8>,
+    },
 }
 
+/// Every event type we can decode, anything else is an `Unknown` event
+const EVENT_TYPES: [&str; 12] = [
+    "Post",
+    "Follow",
+    "Unfollow",
+    "About",
+    "Block",
+    "Unblock",
+    "React",
+    "Unreact",
+    "Edit",
+    "Delete",
+    "RedeemInvite",
+    "Unknown",
+];
+
 impl ButtEvent {
     pub fn to_bytes(&self) -> Vec<u8> {
         serde_json::to_vec(&self).expect("message converted to json")
     }
-    pub fn from_bytes(bytes: Vec<u8>) -> Self {
-        serde_json::from_slice(&bytes).unwrap()
+
+    /// Fails for bodies which are not events at all or are malformed versions of the events we
+    /// know about
+    pub fn from_bytes(bytes: Vec<u8>) -> Result<Self> {
+        let err = match serde_json::from_slice(&bytes) {
+            Ok(event) => return Ok(event),
+            Err(err) => err,
+        };
+
+        // Events are externally tagged, so the type is either the single key of an object or a
+        // bare string for events without content
+        let event_type = match serde_json::from_slice::<Value>(&bytes)? {
+            Value::Object(map) if map.len() == 1 => map.keys().next().cloned(),
+            Value::String(event_type) => Some(event_type),
+            _ => None,
+        };
+        match event_type {
+            Some(event_type) if !EVENT_TYPES.contains(&event_type.as_str()) => {
+                Ok(ButtEvent::Unknown {
+                    event_type,
+                    raw: bytes,
+                })
+            }
+            _ => Err(err.into()),
+        }
     }
 }
 
@@ -64,3 +193,65 @@ where
     let bytes = encode_cbor(&(header.to_bytes(), body.map(|body| body.to_bytes())))?;
     Ok(bytes)
 }
+
+#[cfg(test)]
+mod tests {
+    use p2panda_core::PrivateKey;
+
+    use super::*;
+
+    #[test]
+    fn decodes_known_event() {
+        let public_key = PrivateKey::new().public_key();
+        let bytes = ButtEvent::Follow(public_key).to_bytes();
+
+        let event = ButtEvent::from_bytes(bytes).unwrap();
+        assert!(matches!(event, ButtEvent::Follow(key) if key == public_key));
+    }
+
+    #[test]
+    fn unknown_type_becomes_unknown() {
+        let bytes = br#"{"Poll":{"question":"lunch?"}}"#.to_vec();
+
+        let event = ButtEvent::from_bytes(bytes.clone()).unwrap();
+        assert!(matches!(
+            event,
+            ButtEvent::Unknown { event_type, raw } if event_type == "Poll" && raw == bytes
+        ));
+    }
+
+    #[test]
+    fn malformed_known_type_fails() {
+        assert!(ButtEvent::from_bytes(br#"{"Follow":42}"#.to_vec()).is_err());
+        assert!(ButtEvent::from_bytes(br#"{"React":{"target":"nope"}}"#.to_vec()).is_err());
+    }
+
+    #[test]
+    fn peer_sent_unknown_fails() {
+        let bytes = br#"{"Unknown":{"event_type":"Post","raw":[]}}"#.to_vec();
+        assert!(ButtEvent::from_bytes(bytes).is_err());
+    }
+
+    #[test]
+    fn non_json_fails() {
+        assert!(ButtEvent::from_bytes(b"\xffnot json".to_vec()).is_err());
+        assert!(ButtEvent::from_bytes(b"[1, 2]".to_vec()).is_err());
+    }
+
+    #[test]
+    fn decodes_bare_string_post() {
+        let event = ButtEvent::from_bytes(br#"{"Post":"hello"}"#.to_vec()).unwrap();
+        assert!(matches!(
+            event,
+            ButtEvent::Post(ButtPostContent { body, root: None, parent: None }) if body == "hello"
+        ));
+
+        // Posts without thread references are still written as a bare string
+        let post = ButtEvent::Post(ButtPostContent {
+            body: "hello".to_string(),
+            root: None,
+            parent: None,
+        });
+        assert_eq!(post.to_bytes(), br#"{"Post":"hello"}"#.to_vec());
+    }
+}
@@ -0,0 +1,165 @@
+// bootstrap peers and the address book of peers we met before
+
+use anyhow::{anyhow, Context, Result};
+use p2panda_core::PublicKey;
+use p2panda_net::Network;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::topic::ButtQuery;
+
+/// File in the data directory holding the address book
+const ADDRESS_BOOK_FILE: &str = "peers.json";
+
+/// How often the peers the network knows about are written to the address book
+const SAVE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// A peer we can dial directly, without discovering it first
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct PeerAddress {
+    pub public_key: PublicKey,
+    pub addresses: Vec<SocketAddr>,
+}
+
+/// Parses `<public key>@<socket address>`, give a peer several times for more addresses
+impl FromStr for PeerAddress {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        let (public_key, address) = value
+            .split_once('@')
+            .ok_or_else(|| anyhow!("expected <public key>@<address>"))?;
+        Ok(PeerAddress {
+            public_key: public_key.parse().context("invalid public key")?,
+            addresses: vec![address.parse().context("invalid socket address")?],
+        })
+    }
+}
+
+/// Every peer we met before, keyed by public key and kept in the data directory
+#[derive(Debug, Default)]
+pub struct AddressBook {
+    path: PathBuf,
+    peers: HashMap<PublicKey, Vec<SocketAddr>>,
+}
+
+impl AddressBook {
+    /// A missing or unreadable address book starts out empty
+    pub fn load(data_dir: &Path) -> Self {
+        let path = data_dir.join(ADDRESS_BOOK_FILE);
+        let peers: Vec<PeerAddress> = fs::read(&path)
+            .ok()
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .unwrap_or_default();
+
+        let mut book = AddressBook {
+            path,
+            peers: HashMap::new(),
+        };
+        for peer in peers {
+            book.add(peer);
+        }
+        book
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let peers = self.peers();
+        fs::write(&self.path, serde_json::to_vec_pretty(&peers)?)
+            .with_context(|| format!("could not write {}", self.path.display()))
+    }
+
+    /// Remember a peer, addresses we knew before stay in front of new ones
+    pub fn add(&mut self, peer: PeerAddress) {
+        let addresses = self.peers.entry(peer.public_key).or_default();
+        for address in peer.addresses {
+            if !addresses.contains(&address) {
+                addresses.push(address);
+            }
+        }
+    }
+
+    pub fn peers(&self) -> Vec<PeerAddress> {
+        self.peers
+            .iter()
+            .map(|(public_key, addresses)| PeerAddress {
+                public_key: *public_key,
+                addresses: addresses.clone(),
+            })
+            .collect()
+    }
+
+    /// Keep writing the peers the network learned about into the address book
+    pub fn keep_saving(mut self, network: Network<ButtQuery>) {
+        tokio::task::spawn(async move {
+            loop {
+                tokio::time::sleep(SAVE_INTERVAL).await;
+                let known_peers = match network.known_peers().await {
+                    Ok(known_peers) => known_peers,
+                    Err(err) => {
+                        println!("could not list known peers: {}", err);
+                        continue;
+                    }
+                };
+
+                for node_addr in known_peers {
+                    let Ok(public_key) = PublicKey::from_bytes(node_addr.node_id.as_bytes()) else {
+                        continue;
+                    };
+                    let addresses: Vec<SocketAddr> =
+                        node_addr.direct_addresses.into_iter().collect();
+                    if !addresses.is_empty() {
+                        self.add(PeerAddress {
+                            public_key,
+                            addresses,
+                        });
+                    }
+                }
+                if let Err(err) = self.save() {
+                    println!("could not save the address book: {}", err);
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use p2panda_core::PrivateKey;
+
+    use super::*;
+
+    #[test]
+    fn parses_peer_address() {
+        let public_key = PrivateKey::new().public_key();
+
+        let peer: PeerAddress = format!("{}@192.168.1.2:2022", public_key).parse().unwrap();
+        assert_eq!(peer.public_key, public_key);
+        assert_eq!(
+            peer.addresses,
+            vec!["192.168.1.2:2022".parse::<SocketAddr>().unwrap()]
+        );
+
+        let peer: PeerAddress = format!("{}@[::1]:2022", public_key).parse().unwrap();
+        assert_eq!(
+            peer.addresses,
+            vec!["[::1]:2022".parse::<SocketAddr>().unwrap()]
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_peer_address() {
+        let public_key = PrivateKey::new().public_key();
+
+        assert!("192.168.1.2:2022".parse::<PeerAddress>().is_err());
+        assert!("nope@192.168.1.2:2022".parse::<PeerAddress>().is_err());
+        assert!(format!("{}@192.168.1.2", public_key)
+            .parse::<PeerAddress>()
+            .is_err());
+        assert!(format!("{}@", public_key).parse::<PeerAddress>().is_err());
+    }
+}
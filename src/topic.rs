@@ -11,6 +11,8 @@ type Logs = HashMap<PublicKey, Vec<ButtLogId>>;
 
 #[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct ButtQuery {
+    /// Network the topic belongs to, nodes of other networks never gossip with us
+    pub network: Hash,
     pub hops: u8,
 }
 
@@ -18,7 +20,9 @@ impl TopicQuery for ButtQuery {}
 
 impl TopicId for ButtQuery {
     fn id(&self) -> [u8; 32] {
-        Hash::new("gossip-topic").into()
+        let mut bytes = self.network.as_bytes().to_vec();
+        bytes.extend_from_slice(b"gossip-topic");
+        Hash::new(bytes).into()
     }
 }
 
@@ -27,20 +31,29 @@ pub struct ButtLogMap {
     #[allow(unused)]
     store: OperationStore,
     app_data: AppData,
+    public_key: PublicKey,
 }
 
 impl ButtLogMap {
-    pub fn new(s: OperationStore, app_data: AppData) -> Self {
-        ButtLogMap { store: s, app_data }
+    pub fn new(s: OperationStore, app_data: AppData, public_key: PublicKey) -> Self {
+        ButtLogMap {
+            store: s,
+            app_data,
+            public_key,
+        }
     }
 }
 
 #[async_trait]
 impl TopicLogMap<ButtQuery, ButtLogId> for ButtLogMap {
-    async fn get(&self, _topic: &ButtQuery) -> Option<Logs> {
+    /// Logs of everyone within `hops` follows of our own key, plus our own log
+    async fn get(&self, topic: &ButtQuery) -> Option<Logs> {
         let mut result = HashMap::new();
-        let keys = self.app_data.get_all_keys().await;
-        for public_key in keys {
+        let distances = self
+            .app_data
+            .get_hop_distances(&self.public_key, topic.hops)
+            .await;
+        for public_key in distances.into_keys() {
             result.insert(public_key, vec![ButtLogId(public_key)]);
         }
         Some(result)